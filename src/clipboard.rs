@@ -0,0 +1,500 @@
+use crate::launcher::{self, LaunchError};
+use crate::model::ClipboardContent;
+use base64::prelude::*;
+use log::warn;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// MIME type text is stored and copied with
+const TEXT_MIME_TYPE: &str = "text/plain;charset=utf-8";
+/// Set by password managers on secrets which should not be kept in clipboard history
+const PASSWORD_HINT_MIME_TYPE: &str = "x-kde-passwordManagerHint";
+
+fn env_is_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+fn is_wayland_session() -> bool {
+    env_is_set("WAYLAND_DISPLAY")
+        || std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+}
+
+fn is_x11_session() -> bool {
+    env_is_set("DISPLAY") || std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "x11")
+}
+
+/// Picks a command which pastes the clipboard into the focused window,
+/// or names the missing tools if none is installed
+pub fn paste_preset() -> Result<&'static str, LaunchError> {
+    if is_wayland_session() {
+        if launcher::program_exists("wtype") {
+            return Ok("wtype -M ctrl -k v -m ctrl");
+        }
+        if launcher::program_exists("ydotool") {
+            // Key codes of left ctrl and v
+            return Ok("ydotool key 29:1 47:1 47:0 29:0");
+        }
+        return Err(LaunchError::NotFound("wtype or ydotool".to_string()));
+    }
+    if launcher::program_exists("xdotool") {
+        return Ok("xdotool key --clearmodifiers ctrl+v");
+    }
+    Err(LaunchError::NotFound("xdotool".to_string()))
+}
+
+#[derive(Debug)]
+pub enum ClipboardError {
+    NotFound(String),
+    Spawn(String, std::io::Error),
+    Write(std::io::Error),
+    Failed(String),
+    #[cfg(feature = "wayland")]
+    Read(String),
+    Unsupported(&'static str),
+}
+
+impl Display for ClipboardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(program) => write!(f, "Clipboard command not found: {}", program),
+            Self::Spawn(program, err) => write!(f, "Failed to run {}: {}", program, err),
+            Self::Write(err) => write!(f, "Failed to write to the clipboard: {}", err),
+            Self::Failed(program) => write!(f, "{} failed to copy", program),
+            #[cfg(feature = "wayland")]
+            Self::Read(err) => write!(f, "Failed to read the clipboard: {}", err),
+            Self::Unsupported(backend) => {
+                write!(
+                    f,
+                    "The {} clipboard backend cannot read the clipboard",
+                    backend
+                )
+            }
+        }
+    }
+}
+
+/// Picks the type to record from the offered ones, preferring images over text.
+/// Returns `None` for content without a supported type or marked as a password
+fn preferred_mime_type(types: &[String]) -> Option<String> {
+    if types
+        .iter()
+        .any(|mime_type| mime_type == PASSWORD_HINT_MIME_TYPE)
+    {
+        return None;
+    }
+    let offered = |wanted: &str| types.iter().find(|mime_type| *mime_type == wanted).cloned();
+    offered("image/png")
+        .or_else(|| {
+            types
+                .iter()
+                .find(|mime_type| mime_type.starts_with("image/"))
+                .cloned()
+        })
+        .or_else(|| {
+            [
+                TEXT_MIME_TYPE,
+                "UTF8_STRING",
+                "text/plain",
+                "STRING",
+                "TEXT",
+            ]
+            .into_iter()
+            .find_map(offered)
+        })
+}
+
+/// Text types of X11 and Wayland are all stored as UTF-8 text
+fn normalize_mime_type(mime_type: &str) -> String {
+    if mime_type.starts_with("image/") {
+        mime_type.to_string()
+    } else {
+        TEXT_MIME_TYPE.to_string()
+    }
+}
+
+/// Which selection copied content ends up in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardSelection {
+    Clipboard,
+    Primary,
+    Both,
+}
+
+impl ClipboardSelection {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "clipboard" => Self::Clipboard,
+            "primary" => Self::Primary,
+            "both" => Self::Both,
+            _ => {
+                warn!("No clipboard selection '{name}', falling back to 'clipboard'");
+                Self::Clipboard
+            }
+        }
+    }
+
+    /// Splits `Both` for backends which can only copy to one selection at a time
+    fn single_selections(&self) -> Vec<ClipboardSelection> {
+        match self {
+            Self::Both => vec![Self::Clipboard, Self::Primary],
+            selection => vec![*selection],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardBackend {
+    WlCopy,
+    Xclip,
+    Xsel,
+    /// Serves the content using the Wayland data-control protocol from a detached `os` process
+    #[cfg(feature = "wayland")]
+    DataControl,
+    /// Asks the terminal to copy using the OSC 52 escape sequence
+    Osc52,
+}
+
+impl ClipboardBackend {
+    pub fn from_name(name: &str, ui: &str) -> Self {
+        match name {
+            "auto" => Self::detect(ui),
+            "wl-copy" => Self::WlCopy,
+            "xclip" => Self::Xclip,
+            "xsel" => Self::Xsel,
+            #[cfg(feature = "wayland")]
+            "data-control" => Self::DataControl,
+            "osc52" => Self::Osc52,
+            _ => {
+                warn!("No clipboard backend '{name}', detecting one");
+                Self::detect(ui)
+            }
+        }
+    }
+
+    /// Picks a backend based on the session type
+    fn detect(ui: &str) -> Self {
+        let terminal_ui = ui == "ratatui";
+        if terminal_ui && (env_is_set("SSH_TTY") || env_is_set("SSH_CONNECTION")) {
+            return Self::Osc52;
+        }
+        if is_wayland_session() {
+            #[cfg(feature = "wayland")]
+            return Self::DataControl;
+            #[cfg(not(feature = "wayland"))]
+            return Self::WlCopy;
+        }
+        if is_x11_session() {
+            if launcher::program_exists("xclip") {
+                return Self::Xclip;
+            }
+            return Self::Xsel;
+        }
+        Self::Osc52
+    }
+
+    pub fn copy(
+        &self,
+        content: &ClipboardContent,
+        selection: ClipboardSelection,
+    ) -> Result<(), ClipboardError> {
+        match self {
+            Self::Osc52 => Self::copy_osc52(content, selection),
+            #[cfg(feature = "wayland")]
+            Self::DataControl => {
+                let program = std::env::current_exe()
+                    .map_err(|err| ClipboardError::Spawn(crate::APP_NAME.to_string(), err))?;
+                let mut args = vec![
+                    program.to_string_lossy().to_string(),
+                    "--serve-clipboard".to_string(),
+                    Self::selection_name(selection).to_string(),
+                ];
+                if let Some(mime_type) = &content.mime_type {
+                    args.extend(["--serve-clipboard-type".to_string(), mime_type.clone()]);
+                }
+                // The serving process lives until the content is replaced, do not wait for it
+                Self::pipe(&args, content, false)
+            }
+            _ => {
+                for selection in selection.single_selections() {
+                    let args = self.command(content, selection);
+                    Self::pipe(&args, content, true)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads the clipboard in its preferred type, `None` if it is empty
+    pub fn read(&self) -> Result<Option<ClipboardContent>, ClipboardError> {
+        match self {
+            Self::Osc52 => Err(ClipboardError::Unsupported("osc52")),
+            #[cfg(feature = "wayland")]
+            Self::DataControl => Self::read_data_control(),
+            // xsel only handles text
+            Self::Xsel => Ok(Self::output(&["xsel", "--clipboard", "--output"])?
+                .filter(|data| !data.is_empty())
+                .map(|data| ClipboardContent::new(data, Some(TEXT_MIME_TYPE.to_string())))),
+            _ => {
+                let list = match self {
+                    Self::WlCopy => vec!["wl-paste", "--list-types"],
+                    _ => vec!["xclip", "-selection", "clipboard", "-o", "-t", "TARGETS"],
+                };
+                let Some(types) = Self::output(&list)? else {
+                    return Ok(None);
+                };
+                let types: Vec<String> = String::from_utf8_lossy(&types)
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .collect();
+                let Some(mime_type) = preferred_mime_type(&types) else {
+                    return Ok(None);
+                };
+                let read = match self {
+                    Self::WlCopy => vec!["wl-paste", "--no-newline", "--type", &mime_type],
+                    _ => vec!["xclip", "-selection", "clipboard", "-o", "-t", &mime_type],
+                };
+                Ok(Self::output(&read)?
+                    .filter(|data| !data.is_empty())
+                    .map(|data| ClipboardContent::new(data, Some(normalize_mime_type(&mime_type)))))
+            }
+        }
+    }
+
+    /// Runs `args` and returns its output, `None` if it fails like paste commands do on an empty clipboard
+    fn output(args: &[&str]) -> Result<Option<Vec<u8>>, ClipboardError> {
+        if !launcher::program_exists(args[0]) {
+            return Err(ClipboardError::NotFound(args[0].to_string()));
+        }
+        let output = Command::new(args[0])
+            .args(&args[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|err| ClipboardError::Spawn(args[0].to_string(), err))?;
+        Ok(output.status.success().then_some(output.stdout))
+    }
+
+    #[cfg(feature = "wayland")]
+    fn read_data_control() -> Result<Option<ClipboardContent>, ClipboardError> {
+        use std::io::Read;
+        use wl_clipboard_rs::paste::{
+            get_contents, get_mime_types, ClipboardType, Error, MimeType, Seat,
+        };
+
+        let types = match get_mime_types(ClipboardType::Regular, Seat::Unspecified) {
+            Ok(types) => types.into_iter().collect::<Vec<_>>(),
+            Err(Error::ClipboardEmpty | Error::NoSeats) => return Ok(None),
+            Err(err) => return Err(ClipboardError::Read(err.to_string())),
+        };
+        let Some(mime_type) = preferred_mime_type(&types) else {
+            return Ok(None);
+        };
+        let specific = MimeType::Specific(&mime_type);
+        let mut pipe = match get_contents(ClipboardType::Regular, Seat::Unspecified, specific) {
+            Ok((pipe, _)) => pipe,
+            Err(Error::ClipboardEmpty | Error::NoMimeType) => return Ok(None),
+            Err(err) => return Err(ClipboardError::Read(err.to_string())),
+        };
+        let mut data = vec![];
+        pipe.read_to_end(&mut data)
+            .map_err(|err| ClipboardError::Read(err.to_string()))?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(ClipboardContent::new(
+            data,
+            Some(normalize_mime_type(&mime_type)),
+        )))
+    }
+
+    fn command(&self, content: &ClipboardContent, selection: ClipboardSelection) -> Vec<String> {
+        let primary = selection == ClipboardSelection::Primary;
+        let mut args = vec![];
+        match self {
+            Self::WlCopy => {
+                args.push("wl-copy".to_string());
+                if primary {
+                    args.push("--primary".to_string());
+                }
+                if let Some(mime_type) = &content.mime_type {
+                    args.extend(["--type".to_string(), mime_type.clone()]);
+                }
+            }
+            Self::Xclip => {
+                args.extend(["xclip".to_string(), "-selection".to_string()]);
+                args.push(Self::selection_name(selection).to_string());
+                // Without a target xclip offers text as UTF8_STRING, STRING and TEXT,
+                // a `text/plain` target alone cannot be pasted into most X11 apps
+                let mime_type = content.mime_type.as_ref();
+                if let Some(mime_type) =
+                    mime_type.filter(|mime_type| !mime_type.starts_with("text/"))
+                {
+                    args.extend(["-t".to_string(), mime_type.clone()]);
+                }
+            }
+            Self::Xsel => {
+                // xsel only handles text, the MIME type is dropped
+                args.push("xsel".to_string());
+                args.push(if primary { "--primary" } else { "--clipboard" }.to_string());
+                args.push("--input".to_string());
+            }
+            _ => unreachable!("{:?} does not copy through a command", self),
+        }
+        args
+    }
+
+    fn selection_name(selection: ClipboardSelection) -> &'static str {
+        match selection {
+            ClipboardSelection::Clipboard => "clipboard",
+            ClipboardSelection::Primary => "primary",
+            ClipboardSelection::Both => "both",
+        }
+    }
+
+    /// Runs `args` with the content on stdin
+    fn pipe(args: &[String], content: &ClipboardContent, wait: bool) -> Result<(), ClipboardError> {
+        if !launcher::program_exists(&args[0]) {
+            return Err(ClipboardError::NotFound(args[0].clone()));
+        }
+        let mut child = Command::new(&args[0])
+            .args(&args[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| ClipboardError::Spawn(args[0].clone(), err))?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin
+            .write_all(content.data.as_slice())
+            .map_err(ClipboardError::Write)?;
+        drop(stdin);
+        if wait {
+            let status = child
+                .wait()
+                .map_err(|err| ClipboardError::Spawn(args[0].clone(), err))?;
+            if !status.success() {
+                return Err(ClipboardError::Failed(args[0].clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_osc52(
+        content: &ClipboardContent,
+        selection: ClipboardSelection,
+    ) -> Result<(), ClipboardError> {
+        let target = match selection {
+            ClipboardSelection::Clipboard => "c",
+            ClipboardSelection::Primary => "p",
+            ClipboardSelection::Both => "cp",
+        };
+        let sequence = format!(
+            "\x1b]52;{};{}\x07",
+            target,
+            BASE64_STANDARD.encode(&content.data)
+        );
+        // Write to the terminal directly, stdout may be piped into a script
+        let mut tty = OpenOptions::new()
+            .write(true)
+            .open("/dev/tty")
+            .map_err(ClipboardError::Write)?;
+        tty.write_all(sequence.as_bytes())
+            .map_err(ClipboardError::Write)?;
+        tty.flush().map_err(ClipboardError::Write)
+    }
+}
+
+/// Serves clipboard content read from stdin until it is replaced
+#[cfg(feature = "wayland")]
+pub fn serve(selection: &str, mime_type: Option<&str>) {
+    use std::io::Read;
+    use wl_clipboard_rs::copy::{ClipboardType, MimeType, Options, Source};
+
+    let mut data = vec![];
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut data)
+        .expect("Failed to read from stdin");
+    let clipboard = match ClipboardSelection::from_name(selection) {
+        ClipboardSelection::Clipboard => ClipboardType::Regular,
+        ClipboardSelection::Primary => ClipboardType::Primary,
+        ClipboardSelection::Both => ClipboardType::Both,
+    };
+    let mime_type = match mime_type {
+        Some(mime_type) => MimeType::Specific(mime_type.to_string()),
+        None => MimeType::Autodetect,
+    };
+    let mut options = Options::new();
+    options.clipboard(clipboard).foreground(true);
+    if let Err(err) = options.copy(Source::Bytes(data.into_boxed_slice()), mime_type) {
+        warn!("Failed to serve clipboard: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_copy_commands() {
+        use ClipboardBackend::*;
+        use ClipboardSelection::*;
+        let text = ClipboardContent::text("hello".to_string());
+        let image = ClipboardContent::new(vec![0x89], Some("image/png".to_string()));
+        let cases: [(
+            ClipboardBackend,
+            &ClipboardContent,
+            ClipboardSelection,
+            &str,
+        ); 8] = [
+            (
+                WlCopy,
+                &text,
+                Clipboard,
+                "wl-copy --type text/plain;charset=utf-8",
+            ),
+            (
+                WlCopy,
+                &text,
+                Primary,
+                "wl-copy --primary --type text/plain;charset=utf-8",
+            ),
+            (WlCopy, &image, Clipboard, "wl-copy --type image/png"),
+            (Xclip, &text, Clipboard, "xclip -selection clipboard"),
+            (Xclip, &text, Primary, "xclip -selection primary"),
+            (
+                Xclip,
+                &image,
+                Clipboard,
+                "xclip -selection clipboard -t image/png",
+            ),
+            (Xsel, &text, Clipboard, "xsel --clipboard --input"),
+            (Xsel, &text, Primary, "xsel --primary --input"),
+        ];
+        for (backend, content, selection, expected) in cases {
+            assert_eq!(
+                backend.command(content, selection).join(" "),
+                expected,
+                "{:?} {:?}",
+                backend,
+                selection
+            );
+        }
+    }
+
+    #[test]
+    fn copies_to_both_selections_one_after_another() {
+        let text = ClipboardContent::text("hello".to_string());
+        let commands: Vec<String> = ClipboardSelection::Both
+            .single_selections()
+            .into_iter()
+            .map(|selection| ClipboardBackend::Xclip.command(&text, selection).join(" "))
+            .collect();
+        assert_eq!(
+            commands,
+            vec!["xclip -selection clipboard", "xclip -selection primary"]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::clipboard::{ClipboardBackend, ClipboardError};
+use crate::model::ClipboardContent;
+use crate::opts::Config;
+use crate::source::ClipboardConfig;
+use crate::APP_NAME;
+use log::{error, warn};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::Duration;
+use xdg::BaseDirectories;
+
+pub struct ClipboardEntry {
+    pub hash: String,
+    pub content: ClipboardContent,
+}
+
+/// Clipboard history recorded by `os clipboard-watch`, kept next to the history
+pub struct ClipboardStore {
+    db: Connection,
+}
+
+impl ClipboardStore {
+    pub fn new() -> Self {
+        let xdg_dirs = BaseDirectories::with_prefix(APP_NAME).unwrap();
+        let data_home = xdg_dirs.get_data_home();
+        if !data_home.exists() {
+            std::fs::create_dir_all(&data_home).unwrap();
+        }
+        Self::open(&data_home.join("clipboard.db"))
+    }
+
+    pub fn open(path: &Path) -> Self {
+        let db = Connection::open(path).unwrap();
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS clipboard (
+                hash TEXT PRIMARY KEY,
+                mime_type TEXT,
+                data BLOB,
+                size INTEGER,
+                timestamp INTEGER
+            )",
+            [],
+        )
+        .unwrap();
+        Self { db }
+    }
+
+    /// SHA-256 of the content as hex, stable across builds so stored entries stay deduplicated
+    pub fn hash(data: &[u8]) -> String {
+        Sha256::digest(data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Records the content, moving it to the top if it was copied before.
+    /// Returns false for empty content, content larger than `max_size` bytes or a failed write
+    pub fn add(&self, content: &ClipboardContent, max_size: usize) -> bool {
+        if content.data.is_empty() || content.data.len() > max_size {
+            return false;
+        }
+        let added = self.db.execute(
+            "INSERT INTO clipboard (hash, mime_type, data, size, timestamp)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (hash) DO UPDATE SET timestamp = excluded.timestamp",
+            (
+                Self::hash(&content.data),
+                &content.mime_type,
+                &content.data,
+                content.data.len(),
+                chrono::Utc::now().timestamp_millis(),
+            ),
+        );
+        match added {
+            Ok(_) => true,
+            Err(err) => {
+                warn!("Failed to record clipboard content: {}", err);
+                false
+            }
+        }
+    }
+
+    /// All entries, most recently copied first
+    pub fn entries(&self) -> Vec<ClipboardEntry> {
+        let mut statement = self
+            .db
+            .prepare("SELECT hash, mime_type, data FROM clipboard ORDER BY timestamp DESC")
+            .unwrap();
+        statement
+            .query_map([], |row| {
+                Ok(ClipboardEntry {
+                    hash: row.get(0)?,
+                    content: ClipboardContent::new(row.get(2)?, row.get(1)?),
+                })
+            })
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .collect()
+    }
+
+    /// Drops the oldest entries beyond `max_entries`
+    pub fn prune(&self, max_entries: usize) {
+        let pruned = self.db.execute(
+            "DELETE FROM clipboard WHERE hash NOT IN (
+                    SELECT hash FROM clipboard ORDER BY timestamp DESC LIMIT ?1
+                )",
+            (max_entries,),
+        );
+        if let Err(err) = pruned {
+            warn!("Failed to prune the clipboard history: {}", err);
+        }
+    }
+}
+
+/// Polls the clipboard and records every change until killed
+pub fn watch(config: &Config) {
+    let clipboard_config: ClipboardConfig = config
+        .source
+        .get("clipboard")
+        .cloned()
+        .unwrap_or_default()
+        .try_into()
+        .unwrap();
+    let backend = ClipboardBackend::from_name(&config.clipboard, &config.ui);
+    let store = ClipboardStore::new();
+    let interval = Duration::from_millis(clipboard_config.interval);
+    let mut last_hash = None;
+    loop {
+        match backend.read() {
+            Ok(Some(content)) => {
+                // Only touch the database when the clipboard changed since the last poll
+                let hash = ClipboardStore::hash(&content.data);
+                if last_hash.as_ref() != Some(&hash) {
+                    if store.add(&content, clipboard_config.max_entry_size) {
+                        store.prune(clipboard_config.max_entries);
+                    }
+                    last_hash = Some(hash);
+                }
+            }
+            Ok(None) => {}
+            Err(err @ (ClipboardError::NotFound(_) | ClipboardError::Unsupported(_))) => {
+                error!("{}", err);
+                return;
+            }
+            Err(err) => warn!("{}", err),
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> ClipboardContent {
+        ClipboardContent::new(text.as_bytes().to_vec(), Some("text/plain".to_string()))
+    }
+
+    fn texts(store: &ClipboardStore) -> Vec<String> {
+        store
+            .entries()
+            .into_iter()
+            .map(|entry| String::from_utf8(entry.content.data).unwrap())
+            .collect()
+    }
+
+    fn store() -> (tempfile::TempDir, ClipboardStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ClipboardStore::open(&dir.path().join("clipboard.db"));
+        (dir, store)
+    }
+
+    #[test]
+    fn hashes_with_sha256() {
+        assert_eq!(
+            ClipboardStore::hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn copying_again_moves_the_entry_to_the_top() {
+        let (_dir, store) = store();
+        assert!(store.add(&text("first"), 100));
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(store.add(&text("second"), 100));
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(store.add(&text("first"), 100));
+        assert_eq!(texts(&store), vec!["first", "second"]);
+    }
+
+    #[test]
+    fn skips_empty_and_oversized_content() {
+        let (_dir, store) = store();
+        assert!(!store.add(&text(""), 100));
+        assert!(!store.add(&text("too long"), 7));
+        assert!(store.add(&text("fits"), 4));
+        assert_eq!(texts(&store), vec!["fits"]);
+    }
+
+    #[test]
+    fn prunes_the_oldest_entries() {
+        let (_dir, store) = store();
+        for entry in ["one", "two", "three"] {
+            store.add(&text(entry), 100);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        store.prune(2);
+        assert_eq!(texts(&store), vec!["three", "two"]);
+    }
+}
@@ -1,8 +1,12 @@
+use crate::model::OSImage;
 use crate::APP_NAME;
+use image::ImageReader;
 use serde::{de::DeserializeOwned, Serialize};
 // use serde_json::{from_reader, from_str, to_string};
 use std::{
-    path::PathBuf,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 use toml::{from_str, to_string};
@@ -13,6 +17,12 @@ pub struct Helpers {
 }
 
 impl Helpers {
+    /// Keeps caches in `cache_dir` instead of the XDG cache directory, e.g. a temporary one
+    #[cfg(test)]
+    pub fn with_cache_dir(cache_dir: PathBuf) -> Self {
+        Self { cache_dir }
+    }
+
     pub fn read_cache<T>(&self, key: &str) -> Option<T>
     where
         T: DeserializeOwned,
@@ -50,6 +60,27 @@ impl Helpers {
             true
         }
     }
+
+    /// Decodes an encoded image (PNG, JPEG, ...) into the RGBA format used by the UIs
+    pub fn decode_image(bytes: Vec<u8>) -> Option<OSImage> {
+        let arc_bytes: Arc<[u8]> = bytes.into();
+        let cursor = Cursor::new(arc_bytes);
+        let rgba_image = ImageReader::new(cursor)
+            .with_guessed_format()
+            .ok()?
+            .decode()
+            .ok()?
+            .into_rgba8();
+        let rgba_size = rgba_image.dimensions();
+        let raw_bytes = rgba_image.into_raw();
+        let arc_raw_bytes: Arc<[u8]> = raw_bytes.into();
+        OSImage::from_raw(rgba_size.0, rgba_size.1, arc_raw_bytes)
+    }
+
+    pub fn load_image(path: &Path) -> Option<OSImage> {
+        let bytes = std::fs::read(path).ok()?;
+        Self::decode_image(bytes)
+    }
 }
 
 impl Default for Helpers {
@@ -62,4 +93,3 @@ impl Default for Helpers {
         Self { cache_dir }
     }
 }
-
@@ -2,6 +2,7 @@ use crate::model::SearchItem;
 use crate::APP_NAME;
 use chrono;
 use rusqlite::Connection;
+use std::path::Path;
 use xdg::BaseDirectories;
 
 pub struct History {
@@ -15,8 +16,11 @@ impl History {
         if !data_home.exists() {
             std::fs::create_dir_all(&data_home).unwrap();
         }
-        let data_home = data_home.join("history.db");
-        let db = Connection::open(data_home).unwrap();
+        Self::open(&data_home.join("history.db"))
+    }
+
+    pub fn open(path: &Path) -> Self {
+        let db = Connection::open(path).unwrap();
         db.execute(
             "CREATE TABLE IF NOT EXISTS history (
                 id TEXT,
@@ -44,7 +48,8 @@ impl History {
     pub fn add(&self, item: &SearchItem) {
         self.db
             .execute(
-                "INSERT INTO history (id, timestamp) VALUES (?1, ?2)",
+                // Selecting an item twice within a second, e.g. to confirm it, counts once
+                "INSERT OR IGNORE INTO history (id, timestamp) VALUES (?1, ?2)",
                 (&item.id, &chrono::Utc::now().timestamp()),
             )
             .unwrap();
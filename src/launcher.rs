@@ -0,0 +1,403 @@
+use crate::APP_NAME;
+use log::warn;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use xdg::BaseDirectories;
+
+#[derive(Debug)]
+pub enum LaunchError {
+    EmptyCommand,
+    Tokenize(String),
+    Quote(String),
+    NotFound(String),
+    Spawn(String, std::io::Error),
+    Exit(String, ExitStatus),
+    LogFile(std::io::Error),
+}
+
+impl Display for LaunchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyCommand => write!(f, "Empty command"),
+            Self::Tokenize(action) => write!(f, "Could not parse command: {}", action),
+            Self::Quote(args) => write!(f, "Could not quote command: {}", args),
+            Self::NotFound(program) => write!(f, "Command not found: {}", program),
+            Self::Spawn(program, err) => write!(f, "Failed to run {}: {}", program, err),
+            Self::Exit(program, status) => write!(f, "{} failed: {}", program, status),
+            Self::LogFile(err) => write!(f, "Failed to open log file: {}", err),
+        }
+    }
+}
+
+/// Splits a command line into its arguments using shell quoting rules
+pub fn tokenize(action: &str) -> Result<Vec<String>, LaunchError> {
+    let args = shlex::split(action).ok_or_else(|| LaunchError::Tokenize(action.to_string()))?;
+    if args.is_empty() {
+        return Err(LaunchError::EmptyCommand);
+    }
+    Ok(args)
+}
+
+/// Checks whether `program` is a path to a file or can be found in `PATH`
+pub fn program_exists(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Runs `args` in the working directory and with the environment of `context`,
+/// waiting for it to exit and returning its stdout
+pub fn capture(args: &[String], context: &LaunchContext) -> Result<String, LaunchError> {
+    if args.is_empty() {
+        return Err(LaunchError::EmptyCommand);
+    }
+    let program = context.program(&args[0]);
+    let mut command = Command::new(&program);
+    command.args(&args[1..]);
+    command.stdin(Stdio::null()).stderr(Stdio::null());
+    command.envs(&context.env);
+    if let Some(cwd) = &context.cwd {
+        command.current_dir(cwd);
+    }
+    let output = command
+        .output()
+        .map_err(|err| LaunchError::Spawn(program.clone(), err))?;
+    if !output.status.success() {
+        return Err(LaunchError::Exit(args[0].clone(), output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Picks a terminal template for the given terminal program
+pub fn terminal_preset(terminal: &str) -> &'static str {
+    let name = Path::new(terminal)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(terminal);
+    match name {
+        "kitty" => "{terminal} --directory {cwd} --title {title} {cmd}",
+        "wezterm" => "{terminal} start --cwd {cwd} -- {cmd}",
+        "gnome-terminal" => "{terminal} --working-directory={cwd} --title={title} -- {cmd}",
+        "foot" | "footclient" => "{terminal} --working-directory={cwd} --title={title} {cmd}",
+        "alacritty" => "{terminal} --working-directory {cwd} --title {title} -e {cmd}",
+        "ghostty" => "{terminal} --working-directory={cwd} --title={title} -e {cmd}",
+        "konsole" => "{terminal} --workdir {cwd} -e {cmd}",
+        "xfce4-terminal" => "{terminal} --working-directory={cwd} --title={title} -x {cmd}",
+        "xterm" | "uxterm" => "{terminal} -T {title} -e {cmd}",
+        _ => "{terminal} -e {cmd}",
+    }
+}
+
+/// Expands a terminal template into arguments.
+/// A standalone `{cmd}` word expands to the separate arguments of the command,
+/// inside of a word it expands to the quoted command line.
+pub fn terminal_args(
+    template: &str,
+    terminal: &str,
+    args: &[String],
+    cwd: &str,
+    title: &str,
+) -> Result<Vec<String>, LaunchError> {
+    let command_line = shlex::try_join(args.iter().map(String::as_str))
+        .map_err(|_| LaunchError::Quote(args.join(" ")))?;
+    let mut terminal_args = vec![];
+    for word in tokenize(template)? {
+        if word == "{cmd}" {
+            terminal_args.extend(args.iter().cloned());
+            continue;
+        }
+        terminal_args.push(
+            word.replace("{terminal}", terminal)
+                .replace("{cwd}", cwd)
+                .replace("{title}", title)
+                .replace("{cmd}", &command_line),
+        );
+    }
+    Ok(terminal_args)
+}
+
+/// How spawned applications are detached from the launcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchStrategy {
+    /// Spawn as a direct child of the launcher
+    Direct,
+    /// Double-fork into a new session using `setsid --fork`
+    Setsid,
+    /// Run inside a transient `systemd-run --user --scope` unit
+    SystemdRun,
+    /// Hand the command to `uwsm app`
+    Uwsm,
+}
+
+impl LaunchStrategy {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "direct" => Self::Direct,
+            "setsid" => Self::Setsid,
+            "systemd-run" => Self::SystemdRun,
+            "uwsm" => Self::Uwsm,
+            _ => {
+                warn!("No launch strategy '{name}', falling back to 'direct'");
+                Self::Direct
+            }
+        }
+    }
+}
+
+/// Where stdout and stderr of spawned applications end up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchOutput {
+    Null,
+    /// Forward to the journal using `systemd-cat`
+    Journal,
+    /// Append to `launch.log` in the state directory
+    File,
+}
+
+impl LaunchOutput {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "null" => Self::Null,
+            "journal" => Self::Journal,
+            "file" => Self::File,
+            _ => {
+                warn!("No launch output '{name}', falling back to 'null'");
+                Self::Null
+            }
+        }
+    }
+}
+
+/// Working directory and additional environment of a spawned command.
+/// Under `uwsm` they are passed through `env`, as the app unit does not inherit them
+#[derive(Debug, Clone, Default)]
+pub struct LaunchContext {
+    pub cwd: Option<PathBuf>,
+    pub env: HashMap<String, String>,
+}
+
+impl LaunchContext {
+    /// Resolves a relative program path like `./build.sh` against `cwd`,
+    /// as the launcher itself runs in another directory
+    fn program(&self, program: &str) -> String {
+        match &self.cwd {
+            Some(cwd) if program.contains('/') && Path::new(program).is_relative() => {
+                cwd.join(program).to_string_lossy().to_string()
+            }
+            _ => program.to_string(),
+        }
+    }
+}
+
+pub struct Launcher {
+    strategy: LaunchStrategy,
+    output: LaunchOutput,
+}
+
+impl Launcher {
+    pub fn new(strategy: &str, output: &str) -> Self {
+        Self {
+            strategy: LaunchStrategy::from_name(strategy),
+            output: LaunchOutput::from_name(output),
+        }
+    }
+
+    /// Spawns `args` detached according to the configured strategy.
+    /// `app_id` is used to name the unit, e.g. the desktop file ID of an application.
+    pub fn spawn(&self, args: &[String], app_id: Option<&str>) -> Result<(), LaunchError> {
+        self.spawn_in(args, app_id, &LaunchContext::default())
+    }
+
+    /// Spawns `args` like `spawn`, in the working directory and with the environment of `context`
+    pub fn spawn_in(
+        &self,
+        args: &[String],
+        app_id: Option<&str>,
+        context: &LaunchContext,
+    ) -> Result<(), LaunchError> {
+        if args.is_empty() {
+            return Err(LaunchError::EmptyCommand);
+        }
+        let program = context.program(&args[0]);
+        // Wrapped commands fail inside the wrapper, so check the program up front
+        if !program_exists(&program) {
+            return Err(LaunchError::NotFound(args[0].clone()));
+        }
+        let app_id = app_id.unwrap_or(&args[0]);
+        let mut argv: Vec<String> = vec![];
+        match self.strategy {
+            LaunchStrategy::Direct => {}
+            LaunchStrategy::Setsid => {
+                argv.extend(["setsid".to_string(), "--fork".to_string()]);
+            }
+            LaunchStrategy::SystemdRun => {
+                argv.extend([
+                    "systemd-run".to_string(),
+                    "--user".to_string(),
+                    "--scope".to_string(),
+                    "--quiet".to_string(),
+                    format!("--unit={}", Self::unit_name(app_id)),
+                    "--".to_string(),
+                ]);
+            }
+            LaunchStrategy::Uwsm => {
+                argv.extend([
+                    "uwsm".to_string(),
+                    "app".to_string(),
+                    "-a".to_string(),
+                    app_id.to_string(),
+                    "--".to_string(),
+                ]);
+            }
+        }
+        if self.output == LaunchOutput::Journal {
+            argv.extend([
+                "systemd-cat".to_string(),
+                format!("--identifier={}", app_id),
+                "--".to_string(),
+            ]);
+        }
+        if self.strategy == LaunchStrategy::Uwsm
+            && (context.cwd.is_some() || !context.env.is_empty())
+        {
+            argv.push("env".to_string());
+            if let Some(cwd) = &context.cwd {
+                argv.extend(["-C".to_string(), cwd.to_string_lossy().to_string()]);
+            }
+            argv.extend(
+                context
+                    .env
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value)),
+            );
+        }
+        argv.push(program);
+        argv.extend(args[1..].iter().cloned());
+
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        command.stdin(Stdio::null());
+        command.envs(&context.env);
+        if let Some(cwd) = &context.cwd {
+            command.current_dir(cwd);
+        }
+        match self.output {
+            LaunchOutput::Null | LaunchOutput::Journal => {
+                command.stdout(Stdio::null()).stderr(Stdio::null());
+            }
+            LaunchOutput::File => {
+                let log_file = Self::log_file().map_err(LaunchError::LogFile)?;
+                let log_file_err = log_file.try_clone().map_err(LaunchError::LogFile)?;
+                command.stdout(log_file).stderr(log_file_err);
+            }
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|err| LaunchError::Spawn(argv[0].clone(), err))?;
+        if self.strategy == LaunchStrategy::Setsid {
+            // setsid exits right after forking, reap it
+            let _ = child.wait();
+        }
+        Ok(())
+    }
+
+    fn log_file() -> std::io::Result<std::fs::File> {
+        let xdg = BaseDirectories::with_prefix(APP_NAME)?;
+        let log_path = xdg.place_state_file("launch.log")?;
+        OpenOptions::new().create(true).append(true).open(log_path)
+    }
+
+    /// Builds a unit name following the `app-<launcher>-<app id>-<random>.scope` convention
+    fn unit_name(app_id: &str) -> String {
+        let app_id = app_id.trim_end_matches(".desktop");
+        let random = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        format!(
+            "app-{}-{}-{:x}.scope",
+            APP_NAME,
+            Self::escape_unit_part(app_id),
+            random
+        )
+    }
+
+    /// Escapes a string the way `systemd-escape` does
+    fn escape_unit_part(part: &str) -> String {
+        part.bytes()
+            .enumerate()
+            .map(|(i, byte)| match byte {
+                b'.' if i == 0 => format!("\\x{:02x}", byte),
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b':' | b'_' | b'.' => {
+                    (byte as char).to_string()
+                }
+                b'/' => "-".to_string(),
+                _ => format!("\\x{:02x}", byte),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant};
+
+    /// Writes an executable `build.sh` into `dir`, which runs `body`
+    fn write_script(dir: &Path, body: &str) {
+        let path = dir.join("build.sh");
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn context(cwd: &Path) -> LaunchContext {
+        LaunchContext {
+            cwd: Some(cwd.to_path_buf()),
+            env: HashMap::from([("TARGET".to_string(), "release".to_string())]),
+        }
+    }
+
+    #[test]
+    fn captures_relative_programs_in_the_working_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        write_script(dir.path(), "echo \"$TARGET $(pwd)\"");
+        let output = capture(&["./build.sh".to_string()], &context(dir.path())).unwrap();
+        let cwd = dir.path().canonicalize().unwrap();
+        assert_eq!(output.trim(), format!("release {}", cwd.display()));
+    }
+
+    #[test]
+    fn spawns_relative_programs_in_the_working_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        write_script(dir.path(), "echo \"$TARGET\" > built");
+        let launcher = Launcher::new("direct", "null");
+        launcher
+            .spawn_in(&["./build.sh".to_string()], None, &context(dir.path()))
+            .unwrap();
+
+        let built = dir.path().join("built");
+        let started = Instant::now();
+        while std::fs::read_to_string(&built).map_or(true, |content| content.is_empty()) {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "script did not run"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(std::fs::read_to_string(built).unwrap(), "release\n");
+
+        // Without a working directory the launcher's own is used
+        assert!(matches!(
+            launcher.spawn(&["./build.sh".to_string()], None),
+            Err(LaunchError::NotFound(_))
+        ));
+    }
+}
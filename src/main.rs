@@ -1,9 +1,21 @@
+use clap_serde_derive::clap::Parser;
+
+mod clipboard;
+mod clipboard_store;
 mod helpers;
 mod history;
+mod launcher;
 mod model;
 mod opts;
 mod os;
+#[cfg(feature = "cliphist")]
+mod pins;
 mod source;
+#[cfg(all(
+    test,
+    any(feature = "duckduckgo", feature = "searxng", feature = "linkding")
+))]
+mod test_server;
 mod ui;
 
 pub const APP_NAME: &str = env!("CARGO_PKG_NAME");
@@ -11,7 +23,18 @@ pub const APPLICATION_ID: &str = "sh.data-niklas.os";
 
 fn main() {
     env_logger::init();
-    let config = opts::Args::read_config();
+    let mut args = opts::Args::parse();
+    #[cfg(feature = "wayland")]
+    if let Some(selection) = &args.serve_clipboard {
+        clipboard::serve(selection, args.serve_clipboard_type.as_deref());
+        return;
+    }
+    let command = args.command.take();
+    let config = args.read_config();
+    if let Some(opts::Command::ClipboardWatch) = command {
+        clipboard_store::watch(&config);
+        return;
+    }
     let ui_type = config.ui.clone();
     let app = os::Os::new(config);
     let mut ui = ui::load_ui(ui_type, app);
@@ -1,22 +1,25 @@
 use crate::os::Os;
 use image::{ImageBuffer, Rgba};
-use std::io::Write;
-use std::process::{Command, Stdio};
+use std::rc::Rc;
 use std::sync::Arc;
 
 #[derive(Clone)]
-pub struct ClipboardContent(pub Vec<u8>);
+pub struct ClipboardContent {
+    pub data: Vec<u8>,
+    /// Detected by the clipboard backend if not set
+    pub mime_type: Option<String>,
+}
 
 impl ClipboardContent {
-    pub fn copy(self) {
-        let copy_command = Command::new("wl-copy")
-            .stdin(Stdio::piped())
-            .spawn()
-            .expect("Failed to spawn command");
-        let mut stdin = copy_command.stdin.unwrap();
-        stdin
-            .write_all(self.0.as_slice())
-            .expect("Failed to write to stdin");
+    pub fn new(data: Vec<u8>, mime_type: Option<String>) -> Self {
+        Self { data, mime_type }
+    }
+
+    pub fn text(text: String) -> Self {
+        Self::new(
+            text.into_bytes(),
+            Some("text/plain;charset=utf-8".to_string()),
+        )
     }
 }
 
@@ -38,6 +41,7 @@ pub enum ItemLayer {
     Top,
 }
 
+#[derive(Clone)]
 pub struct SearchItem {
     pub id: String,
     pub title: Option<String>,
@@ -45,11 +49,28 @@ pub struct SearchItem {
     pub icon: Option<OSImage>,
     pub image: Option<OSImage>,
     pub score: i64,
-    pub action: Box<dyn Fn(&mut Os) -> bool>,
+    pub action: Rc<dyn Fn(&mut Os) -> bool>,
     pub layer: ItemLayer,
     pub source: &'static str,
 }
 
+impl SearchItem {
+    /// An item describing a failed action
+    pub fn error(message: String) -> Self {
+        SearchItem {
+            id: "error".to_string(),
+            title: Some("Error".to_string()),
+            subtitle: Some(message),
+            icon: None,
+            image: None,
+            score: 0,
+            action: Rc::new(|_| false),
+            layer: ItemLayer::Top,
+            source: "os",
+        }
+    }
+}
+
 impl std::hash::Hash for SearchItem {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.id.hash(state);
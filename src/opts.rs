@@ -1,5 +1,10 @@
 use crate::APP_NAME;
-use clap_serde_derive::{clap, clap::Parser, serde::Deserialize, ClapSerde};
+use clap_serde_derive::{
+    clap,
+    clap::{Parser, Subcommand},
+    serde::Deserialize,
+    ClapSerde,
+};
 use std::{collections::HashMap, path::PathBuf};
 
 use xdg::BaseDirectories;
@@ -11,14 +16,33 @@ pub struct Args {
     #[clap(short, long = "config", env, default_value = Config::default_path().into_os_string())]
     pub config_path: std::path::PathBuf,
 
+    /// Serve clipboard content from stdin to the given selection, used by the data-control backend
+    #[clap(long, hide = true)]
+    #[cfg(feature = "wayland")]
+    pub serve_clipboard: Option<String>,
+
+    /// MIME type of the served clipboard content
+    #[clap(long, hide = true)]
+    #[cfg(feature = "wayland")]
+    pub serve_clipboard_type: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Rest of arguments
     #[command(flatten)]
     pub config: <Config as ClapSerde>::Opt,
 }
 
+#[derive(Subcommand)]
+pub enum Command {
+    /// Record clipboard changes into the history of the clipboard source
+    ClipboardWatch,
+}
+
 impl Args {
-    pub fn read_config() -> Config {
-        let mut args = Args::parse();
+    pub fn read_config(self) -> Config {
+        let mut args = self;
         let config_path = args.config_path;
         let config = match std::fs::read_to_string(config_path) {
             Ok(config) => config,
@@ -59,8 +83,45 @@ pub struct Config {
     #[clap(short, long)]
     pub terminal: String,
 
+    /// Template used to run commands in the terminal, e.g. "wezterm start --cwd {cwd} -- {cmd}".
+    /// Supports {terminal}, {cmd}, {cwd} and {title}. Picked from built-in presets when empty
+    #[default(String::new())]
+    #[clap(long)]
+    pub terminal_command: String,
+
+    /// How launched programs are detached: direct, setsid, systemd-run or uwsm
+    #[default("direct".to_string())]
+    #[clap(long)]
+    pub launch_strategy: String,
+
+    /// Where the output of launched programs goes: null, journal or file
+    #[default("null".to_string())]
+    #[clap(long)]
+    pub launch_output: String,
+
+    /// Clipboard backend: auto, wl-copy, xclip, xsel, data-control or osc52
+    #[default("auto".to_string())]
+    #[clap(long)]
+    pub clipboard: String,
+
+    /// Selection to copy into: clipboard, primary or both
+    #[default("clipboard".to_string())]
+    #[clap(long)]
+    pub clipboard_selection: String,
+
+    /// Command which pastes into the focused window, e.g. "wtype -M ctrl -k v -m ctrl".
+    /// Picked based on the session type when empty
+    #[default(String::new())]
+    #[clap(long)]
+    pub paste_command: String,
+
+    /// Milliseconds to wait for the launcher window to close before pasting
+    #[default(200)]
+    #[clap(long)]
+    pub paste_delay: u64,
+
     #[default(100)]
-    #[clap(short, long)]
+    #[clap(short, long, short_alias = 'l')]
     pub maximum_list_item_count: usize,
 
     #[default(false)]
@@ -69,8 +130,53 @@ pub struct Config {
     pub wayland_layer: bool,
 
     #[default(false)]
-    #[clap(short, long, action)]
+    #[clap(long, action)]
     pub initial_search: bool,
+
+    /// Match case-insensitively instead of using smart case
+    #[default(false)]
+    #[clap(short = 'i', long, action)]
+    pub case_insensitive: bool,
+
+    /// Output format of selected stdin items: s (string), i (index), f (filter), q (quoted string)
+    #[default("s".to_string())]
+    #[clap(long)]
+    pub format: String,
+
+    /// Only accept items read from stdin instead of also the typed text
+    #[default(false)]
+    #[clap(long, action)]
+    pub only_match: bool,
+
+    /// How stdin lines are parsed: plain, rofi (row options after \0) or json (one object per line)
+    #[default("plain".to_string())]
+    #[clap(long)]
+    pub input_format: String,
+
+    /// Splits plain stdin lines into columns, e.g. '\t'
+    #[default(String::new())]
+    #[clap(long)]
+    pub delimiter: String,
+
+    /// Columns shown as the title when using a delimiter, e.g. "1" or "1,3"
+    #[default(String::new())]
+    #[clap(long)]
+    pub with_nth: String,
+
+    /// Column shown as the subtitle when using a delimiter
+    #[default(0)]
+    #[clap(long)]
+    pub display_subtitle: usize,
+
+    /// Allow marking several items with Tab, which are all selected with Enter
+    #[default(false)]
+    #[clap(long, action)]
+    pub multi: bool,
+
+    /// Read null-separated instead of newline-separated items from stdin
+    #[default(false)]
+    #[clap(long, action)]
+    pub read0: bool,
 }
 
 impl Config {
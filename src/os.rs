@@ -1,5 +1,7 @@
+use crate::clipboard::{self, ClipboardBackend, ClipboardError, ClipboardSelection};
 use crate::helpers::Helpers;
 use crate::history::History;
+use crate::launcher::{self, LaunchContext, LaunchError, Launcher};
 use crate::model::{ClipboardContent, SearchItem};
 use crate::opts::Config;
 
@@ -10,16 +12,19 @@ use crate::source::LinkdingSource;
 
 #[cfg(feature = "duckduckgo")]
 use crate::source::DuckduckgoSource;
+#[cfg(feature = "searxng")]
+use crate::source::SearxngSource;
 
 use crate::source::{
-    ApplicationsSource, EvalSource, HistorySource, HstrSource, RunCommandsSource,
-    SearchSitesSource, Source, StdinSource, SystemctlSource, ZoxideSource,
+    ApplicationsSource, CalculatorSource, ClipboardSource, CurrencySource, DateTimeSource,
+    EvalSource, HistorySource, HstrSource, RunCommandsSource, SearchSitesSource, Source,
+    StdinSource, SystemctlSource, ZoxideSource,
 };
 
-use shlex::{self, Shlex};
-use std::process::Command;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::{collections::HashMap, process::Stdio};
 
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use rayon::prelude::*;
@@ -31,6 +36,11 @@ pub struct Os {
     pub config: Config,
     history: History,
     helpers: Arc<Helpers>,
+    launcher: Launcher,
+    clipboard: ClipboardBackend,
+    clipboard_selection: ClipboardSelection,
+    error: Option<String>,
+    query: Option<String>,
 }
 
 impl Os {
@@ -85,6 +95,10 @@ impl Os {
             .collect()
     }
 
+    pub fn loading(&self) -> bool {
+        self.sources.values().any(|source| source.loading())
+    }
+
     pub fn deinit(&mut self) {
         let sources = &mut self.sources;
         for (_, source) in sources.iter_mut() {
@@ -97,41 +111,139 @@ impl Os {
         println!("{}", text);
     }
 
-    pub fn run(&self, action: &str) {
-        let args: Vec<_> = Shlex::new(&action).into_iter().collect();
-        let mut command = Command::new(&args[0]);
-        command.args(&args[1..]);
-        command
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null());
-        command.spawn().expect("Failed to spawn command");
+    pub fn run(&self, action: &str) -> Result<(), LaunchError> {
+        self.run_app(&launcher::tokenize(action)?, None)
+    }
+
+    /// Runs `args`, naming its unit after `app_id` (e.g. a desktop file ID)
+    pub fn run_app(&self, args: &[String], app_id: Option<&str>) -> Result<(), LaunchError> {
+        self.launcher.spawn(args, app_id)
+    }
+
+    /// Runs `args` in a terminal using the `terminal_command` template,
+    /// starting in `cwd` or the current directory.
+    /// The terminal itself starts in `cwd` too, for templates without `{cwd}`
+    pub fn run_app_in_terminal(
+        &self,
+        args: &[String],
+        app_id: Option<&str>,
+        cwd: Option<&str>,
+    ) -> Result<(), LaunchError> {
+        let terminal_args = self.terminal_args(args, cwd)?;
+        let context = LaunchContext {
+            cwd: cwd.map(PathBuf::from),
+            ..Default::default()
+        };
+        self.launcher.spawn_in(&terminal_args, app_id, &context)
+    }
+
+    /// Runs `args` in the working directory and with the environment of `context`,
+    /// inside a terminal if `in_terminal` is set
+    pub fn run_command(
+        &self,
+        args: &[String],
+        in_terminal: bool,
+        context: &LaunchContext,
+    ) -> Result<(), LaunchError> {
+        if !in_terminal {
+            return self.launcher.spawn_in(args, None, context);
+        }
+        let cwd = context.cwd.as_ref().map(|cwd| cwd.to_string_lossy());
+        let terminal_args = self.terminal_args(args, cwd.as_deref())?;
+        self.launcher.spawn_in(&terminal_args, None, context)
+    }
+
+    fn terminal_args(
+        &self,
+        args: &[String],
+        cwd: Option<&str>,
+    ) -> Result<Vec<String>, LaunchError> {
+        if args.is_empty() {
+            return Err(LaunchError::EmptyCommand);
+        }
+        let terminal = &self.config.terminal;
+        let template = if self.config.terminal_command.is_empty() {
+            launcher::terminal_preset(terminal)
+        } else {
+            &self.config.terminal_command
+        };
+        let cwd = cwd.map(PathBuf::from).unwrap_or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .or_else(|| std::env::var_os("HOME").map(PathBuf::from))
+                .unwrap_or_else(|| PathBuf::from("/"))
+        });
+        let title = Path::new(&args[0])
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&args[0]);
+        launcher::terminal_args(template, terminal, args, &cwd.to_string_lossy(), title)
+    }
+
+    pub fn copy_to_clipboard(&self, content: ClipboardContent) -> Result<(), ClipboardError> {
+        self.clipboard.copy(&content, self.clipboard_selection)
+    }
+
+    /// Pastes the clipboard into the previously focused window once the launcher has closed,
+    /// using `paste_command` or a preset for the session type
+    pub fn paste(&self) -> Result<(), LaunchError> {
+        let paste_command = if self.config.paste_command.is_empty() {
+            clipboard::paste_preset()?
+        } else {
+            &self.config.paste_command
+        };
+        let delay = self.config.paste_delay as f64 / 1000.;
+        let args = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("sleep {:.3}; exec {}", delay, paste_command),
+        ];
+        self.launcher.spawn(&args, None)
+    }
+
+    /// Copies `content` and pastes it with `paste`, reporting failures.
+    /// Returns whether everything succeeded, to be used as the result of an item action
+    pub fn copy_and_paste(&mut self, content: ClipboardContent, paste: bool) -> bool {
+        let result = self.copy_to_clipboard(content);
+        let copied = self.report(result);
+        if copied && paste {
+            let result = self.paste();
+            return self.report(result);
+        }
+        copied
     }
 
-    pub fn run_in_terminal(&self, action: &str) {
-        let terminal_command = &self.config.terminal;
+    pub fn open_url(&self, url: &str) -> Result<(), LaunchError> {
+        let args = vec!["xdg-open".to_string(), url.to_string()];
+        self.launcher.spawn(&args, None)
+    }
 
-        let mut command = Command::new(terminal_command);
-        command.arg("-e").arg(action);
-        command
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null());
-        command.spawn().expect("Failed to spawn command");
+    /// Remembers a failed action so the UI can display it.
+    /// Returns whether the action succeeded, to be used as the result of an item action.
+    pub fn report<E: Display>(&mut self, result: Result<(), E>) -> bool {
+        match result {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("{}", err);
+                self.error = Some(err.to_string());
+                false
+            }
+        }
     }
 
-    pub fn copy_to_clipboard(&self, content: ClipboardContent) {
-        content.copy();
+    /// Replaces the query once an action keeps the launcher open, e.g. to complete a tag
+    pub fn set_query(&mut self, query: String) {
+        self.query = Some(query);
     }
 
-    pub fn open_url(&self, url: &str) {
-        let mut command = Command::new("xdg-open");
-        command.arg(url);
-        command
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null());
-        command.spawn().expect("Failed to spawn command");
+    /// Takes the query set by the last action, UIs clear the query if there is none
+    pub fn take_query(&mut self) -> Option<String> {
+        self.query.take()
+    }
+
+    /// Takes the last reported error as an item to display
+    pub fn take_error(&mut self) -> Option<SearchItem> {
+        self.error.take().map(SearchItem::error)
     }
 
     pub fn select(&mut self, item: &crate::model::SearchItem) -> bool {
@@ -139,17 +251,31 @@ impl Os {
         (item.action)(self)
     }
 
-    fn load_sources(enabled_sources: &Vec<String>) -> Vec<Box<dyn Source + Send + Sync>> {
+    /// Selects marked items in order, e.g. printing one stdin item per line.
+    /// Returns whether every action finished
+    pub fn select_many(&mut self, items: &[SearchItem]) -> bool {
+        let mut finished = true;
+        for item in items {
+            finished &= self.select(item);
+        }
+        finished
+    }
+
+    fn load_sources(config: &Config) -> Vec<Box<dyn Source + Send + Sync>> {
         let mut sources: Vec<Box<dyn Source + Send + Sync>> = vec![];
-        for name in enabled_sources {
+        for name in &config.sources {
             match name.as_str() {
-                "stdin" => sources.push(Box::new(StdinSource::new())),
+                "stdin" => sources.push(Box::new(StdinSource::new(config))),
                 "eval" => sources.push(Box::new(EvalSource::new())),
+                "calculator" => sources.push(Box::new(CalculatorSource::new())),
+                "datetime" => sources.push(Box::new(DateTimeSource::new())),
+                "currency" => sources.push(Box::new(CurrencySource::new())),
                 "hstr" => sources.push(Box::new(HstrSource::new())),
                 "search_sites" => sources.push(Box::new(SearchSitesSource::new())),
                 "history" => sources.push(Box::new(HistorySource::new())),
                 #[cfg(feature = "cliphist")]
                 "cliphist" => sources.push(Box::new(CliphistSource::new())),
+                "clipboard" => sources.push(Box::new(ClipboardSource::new())),
                 "zoxide" => sources.push(Box::new(ZoxideSource::new())),
                 "applications" => sources.push(Box::new(ApplicationsSource::new())),
                 "systemctl" => sources.push(Box::new(SystemctlSource::new())),
@@ -158,6 +284,8 @@ impl Os {
                 "linkding" => sources.push(Box::new(LinkdingSource::new())),
                 #[cfg(feature = "duckduckgo")]
                 "duckduckgo" => sources.push(Box::new(DuckduckgoSource::new())),
+                #[cfg(feature = "searxng")]
+                "searxng" => sources.push(Box::new(SearxngSource::new())),
                 _ => {
                     warn!("No source '{name}'");
                 }
@@ -168,20 +296,84 @@ impl Os {
     }
 
     pub fn new(config: Config) -> Self {
-        let matcher = Box::new(SkimMatcherV2::default());
-        let sources: Vec<Box<dyn Source + Send + Sync>> = Self::load_sources(&config.sources);
+        let sources = Self::load_sources(&config);
+        let mut os = Self::with_parts(config, sources, History::new(), Helpers::default());
+        os.init_sources();
+        os
+    }
+
+    /// An `Os` without sources, keeping history and caches in `data_dir`, e.g. a temporary one
+    #[cfg(test)]
+    pub fn with_data_dir(config: Config, data_dir: &Path) -> Self {
+        let history = History::open(&data_dir.join("history.db"));
+        let helpers = Helpers::with_cache_dir(data_dir.to_path_buf());
+        Self::with_parts(config, vec![], history, helpers)
+    }
+
+    fn with_parts(
+        config: Config,
+        sources: Vec<Box<dyn Source + Send + Sync>>,
+        history: History,
+        helpers: Helpers,
+    ) -> Self {
+        let matcher = if config.case_insensitive {
+            Box::new(SkimMatcherV2::default().ignore_case())
+        } else {
+            Box::new(SkimMatcherV2::default())
+        };
         let sources = sources
             .into_iter()
             .map(|s| (s.name().to_string(), s))
             .collect();
-        let mut config = Self {
-            history: History::new(),
+        let launcher = Launcher::new(&config.launch_strategy, &config.launch_output);
+        let clipboard = ClipboardBackend::from_name(&config.clipboard, &config.ui);
+        let clipboard_selection = ClipboardSelection::from_name(&config.clipboard_selection);
+        Self {
+            history,
+            launcher,
+            clipboard,
+            clipboard_selection,
+            error: None,
+            query: None,
             matcher,
             sources,
             config,
-            helpers: Arc::new(Helpers::default()),
+            helpers: Arc::new(helpers),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn pastes_with_the_configured_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("paste.sh");
+        let pasted = dir.path().join("pasted");
+        std::fs::write(
+            &stub,
+            format!("#!/bin/sh\nprintf '%s ' \"$@\" > {}\n", pasted.display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config {
+            paste_command: format!("{} key 'ctrl+v'", stub.display()),
+            paste_delay: 0,
+            ..Config::default()
         };
-        config.init_sources();
-        config
+        let os = Os::with_data_dir(config, dir.path());
+        os.paste().unwrap();
+        let started = Instant::now();
+        while std::fs::read_to_string(&pasted).unwrap_or_default() != "key ctrl+v " {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "stub did not run"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
     }
 }
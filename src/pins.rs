@@ -0,0 +1,62 @@
+use crate::APP_NAME;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::path::Path;
+use xdg::BaseDirectories;
+
+/// Item ids pinned by the user, kept next to the history
+pub struct Pins {
+    db: Connection,
+}
+
+impl Pins {
+    pub fn new() -> Self {
+        let xdg_dirs = BaseDirectories::with_prefix(APP_NAME).unwrap();
+        let data_home = xdg_dirs.get_data_home();
+        if !data_home.exists() {
+            std::fs::create_dir_all(&data_home).unwrap();
+        }
+        Self::open(&data_home.join("pins.db"))
+    }
+
+    pub fn open(path: &Path) -> Self {
+        let db = Connection::open(path).unwrap();
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS pins (
+                id TEXT PRIMARY KEY,
+                timestamp INTEGER
+            )",
+            [],
+        )
+        .unwrap();
+        Self { db }
+    }
+
+    pub fn ids(&self) -> HashSet<String> {
+        let mut statement = self.db.prepare("SELECT id FROM pins").unwrap();
+        statement
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .filter_map(|id| id.ok())
+            .collect()
+    }
+
+    /// Pins or unpins an item, returning whether it is pinned now
+    pub fn toggle(&self, id: &str) -> rusqlite::Result<bool> {
+        let removed = self.db.execute("DELETE FROM pins WHERE id = ?1", (id,))?;
+        if removed > 0 {
+            return Ok(false);
+        }
+        self.db.execute(
+            "INSERT INTO pins (id, timestamp) VALUES (?1, ?2)",
+            (id, &chrono::Utc::now().timestamp()),
+        )?;
+        Ok(true)
+    }
+
+    pub fn remove(&self, id: &str) -> rusqlite::Result<()> {
+        self.db
+            .execute("DELETE FROM pins WHERE id = ?1", (id,))
+            .map(|_| ())
+    }
+}
@@ -1,16 +1,16 @@
 use super::Source;
 use crate::helpers::Helpers;
+use crate::launcher::{self, LaunchError};
 use crate::model::OSImage;
 use freedesktop_desktop_entry::{default_paths, DesktopEntry, Iter, PathSource};
 use freedesktop_icon_lookup::Cache;
 
 use rayon::prelude::*;
 
-use image::io::Reader as ImageReader;
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
 
-use std::io::Cursor;
+use std::rc::Rc;
 use std::time::Duration;
 use std::{
     borrow::Cow,
@@ -36,6 +36,8 @@ pub struct ApplicationsConfig {
 
 #[derive(Serialize, Deserialize)]
 struct LoadedDesktopEntry {
+    #[serde(default)]
+    id: String,
     name: String,
     description: String,
     icon: Option<PathBuf>,
@@ -70,6 +72,7 @@ impl LoadedDesktopEntry {
 
         let terminal = entry.terminal();
         Some(LoadedDesktopEntry {
+            id: entry.id().to_string(),
             name,
             description,
             icon,
@@ -89,6 +92,7 @@ impl LoadedDesktopEntry {
 
 #[derive(Clone)]
 pub struct ParsedDesktopEntry {
+    pub id: String,
     pub name: String,
     pub description: String,
     pub icon: Option<OSImage>,
@@ -99,25 +103,14 @@ pub struct ParsedDesktopEntry {
 impl ParsedDesktopEntry {
     fn from_loaded(entry: LoadedDesktopEntry, icons: bool) -> Self {
         let icon = if icons {
-            entry.icon.and_then(|icon_path| {
-                let bytes = fs::read(&icon_path).ok()?;
-                let arc_bytes: Arc<[u8]> = bytes.into();
-                let cursor = Cursor::new(arc_bytes);
-                let rgba_image = ImageReader::new(cursor)
-                    .with_guessed_format()
-                    .ok()?
-                    .decode()
-                    .ok()?
-                    .into_rgba8();
-                let rgba_size = rgba_image.dimensions();
-                let raw_bytes = rgba_image.into_raw();
-                let arc_raw_bytes: Arc<[u8]> = raw_bytes.into();
-                OSImage::from_raw(rgba_size.0, rgba_size.1, arc_raw_bytes)
-            })
+            entry
+                .icon
+                .and_then(|icon_path| Helpers::load_image(&icon_path))
         } else {
             None
         };
         Self {
+            id: entry.id,
             name: entry.name,
             description: entry.description,
             icon,
@@ -127,6 +120,19 @@ impl ParsedDesktopEntry {
     }
 }
 
+/// Splits an `Exec` key into arguments, dropping the field codes we never fill in
+fn exec_args(exec: &str) -> Result<Vec<String>, LaunchError> {
+    let args: Vec<String> = launcher::tokenize(exec)?
+        .into_iter()
+        .filter(|arg| !(arg.len() == 2 && arg.starts_with('%') && arg != "%%"))
+        .map(|arg| arg.replace("%%", "%"))
+        .collect();
+    if args.is_empty() {
+        return Err(LaunchError::EmptyCommand);
+    }
+    Ok(args)
+}
+
 struct ApplicationsSourceInner {
     pub entries: Mutex<Vec<ParsedDesktopEntry>>,
 }
@@ -218,6 +224,7 @@ impl Source for ApplicationsSource {
             .map(|(entry, score)| {
                 let exec = entry.exec.clone();
                 let terminal = entry.terminal.clone();
+                let desktop_id = entry.id.clone();
                 crate::model::SearchItem {
                     id: self.name().to_string() + &entry.name,
                     title: Some(entry.name.clone()),
@@ -226,19 +233,16 @@ impl Source for ApplicationsSource {
                     image: None,
                     score,
                     source: self.name(),
-                    action: Box::new(move |os| {
-                        let exec = exec.clone();
-                        let exec = exec
-                            .replace(" %U", "")
-                            .replace(" %u", "")
-                            .replace(" %F", "")
-                            .replace(" %f", "");
-                        if terminal {
-                            os.run_in_terminal(&exec);
-                        } else {
-                            os.run(&exec);
-                        }
-                        true
+                    action: Rc::new(move |os| {
+                        let app_id = Some(desktop_id.as_str()).filter(|id| !id.is_empty());
+                        let result = exec_args(&exec).and_then(|args| {
+                            if terminal {
+                                os.run_app_in_terminal(&args, app_id, None)
+                            } else {
+                                os.run_app(&args, app_id)
+                            }
+                        });
+                        os.report(result)
                     }),
                     layer: crate::model::ItemLayer::Middle,
                 }
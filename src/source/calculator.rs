@@ -0,0 +1,938 @@
+use crate::helpers::Helpers;
+use crate::model::{ClipboardContent, SearchItem};
+use crate::source::Source;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+fn _default_precision() -> usize {
+    10
+}
+
+#[derive(Deserialize)]
+pub struct CalculatorConfig {
+    /// Paste the result into the focused window after copying
+    #[serde(default)]
+    pub paste: bool,
+    /// Maximum number of decimal places shown
+    #[serde(default = "_default_precision")]
+    pub precision: usize,
+}
+
+/// Kept in the cache between runs, so `ans` and variables survive closing the launcher
+#[derive(Serialize, Deserialize, Default)]
+struct CalculatorState {
+    ans: f64,
+    variables: HashMap<String, f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CalcError {
+    Syntax,
+    UnknownName,
+    /// Units of different dimensions were combined
+    Units,
+    /// Only unitless integers can be shown in other bases
+    NotInteger,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Data,
+    Temperature,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Unit {
+    symbol: &'static str,
+    dimension: Dimension,
+    /// Multiplier to the base unit of the dimension: metre, gram, byte or kelvin
+    factor: f64,
+    /// Added after multiplying, only temperatures have one
+    offset: f64,
+}
+
+impl Unit {
+    fn from_name(name: &str) -> Option<Self> {
+        use Dimension::*;
+        let (symbol, dimension, factor, offset) = match name {
+            "m" | "meter" | "meters" | "metre" | "metres" => ("m", Length, 1.0, 0.0),
+            "km" | "kilometer" | "kilometers" => ("km", Length, 1e3, 0.0),
+            "cm" | "centimeter" | "centimeters" => ("cm", Length, 1e-2, 0.0),
+            "mm" | "millimeter" | "millimeters" => ("mm", Length, 1e-3, 0.0),
+            "um" | "µm" | "micrometer" | "micrometers" => ("µm", Length, 1e-6, 0.0),
+            "nm" | "nanometer" | "nanometers" => ("nm", Length, 1e-9, 0.0),
+            "mi" | "mile" | "miles" => ("mi", Length, 1609.344, 0.0),
+            "yd" | "yard" | "yards" => ("yd", Length, 0.9144, 0.0),
+            "ft" | "foot" | "feet" => ("ft", Length, 0.3048, 0.0),
+            "in" | "inch" | "inches" => ("in", Length, 0.0254, 0.0),
+            "nmi" => ("nmi", Length, 1852.0, 0.0),
+            "g" | "gram" | "grams" => ("g", Mass, 1.0, 0.0),
+            "kg" | "kilogram" | "kilograms" => ("kg", Mass, 1e3, 0.0),
+            "mg" | "milligram" | "milligrams" => ("mg", Mass, 1e-3, 0.0),
+            "t" | "tonne" | "tonnes" => ("t", Mass, 1e6, 0.0),
+            "lb" | "lbs" | "pound" | "pounds" => ("lb", Mass, 453.59237, 0.0),
+            "oz" | "ounce" | "ounces" => ("oz", Mass, 28.349523125, 0.0),
+            "st" | "stone" => ("st", Mass, 6350.29318, 0.0),
+            "bit" | "bits" => ("bit", Data, 0.125, 0.0),
+            "B" | "byte" | "bytes" => ("B", Data, 1.0, 0.0),
+            "kB" | "KB" => ("kB", Data, 1e3, 0.0),
+            "MB" => ("MB", Data, 1e6, 0.0),
+            "GB" => ("GB", Data, 1e9, 0.0),
+            "TB" => ("TB", Data, 1e12, 0.0),
+            "PB" => ("PB", Data, 1e15, 0.0),
+            "KiB" => ("KiB", Data, 1024.0, 0.0),
+            "MiB" => ("MiB", Data, 1024.0 * 1024.0, 0.0),
+            "GiB" => ("GiB", Data, 1024.0 * 1024.0 * 1024.0, 0.0),
+            "TiB" => ("TiB", Data, 1024.0 * 1024.0 * 1024.0 * 1024.0, 0.0),
+            "K" | "kelvin" => ("K", Temperature, 1.0, 0.0),
+            "C" | "°C" | "celsius" => ("°C", Temperature, 1.0, 273.15),
+            "F" | "°F" | "fahrenheit" => ("°F", Temperature, 5.0 / 9.0, 273.15 - 32.0 * 5.0 / 9.0),
+            _ => return None,
+        };
+        Some(Self {
+            symbol,
+            dimension,
+            factor,
+            offset,
+        })
+    }
+}
+
+fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" | "π" => Some(std::f64::consts::PI),
+        "e" => Some(std::f64::consts::E),
+        "tau" | "τ" => Some(std::f64::consts::TAU),
+        "phi" | "φ" => Some(1.618_033_988_749_895),
+        _ => None,
+    }
+}
+
+fn call(name: &str, args: &[f64]) -> Option<f64> {
+    let value = match (name, args) {
+        ("sqrt", [x]) => x.sqrt(),
+        ("cbrt", [x]) => x.cbrt(),
+        ("abs", [x]) => x.abs(),
+        ("exp", [x]) => x.exp(),
+        ("ln", [x]) => x.ln(),
+        ("log" | "log10", [x]) => x.log10(),
+        ("log", [x, base]) => x.log(*base),
+        ("log2", [x]) => x.log2(),
+        ("sin", [x]) => x.sin(),
+        ("cos", [x]) => x.cos(),
+        ("tan", [x]) => x.tan(),
+        ("asin", [x]) => x.asin(),
+        ("acos", [x]) => x.acos(),
+        ("atan", [x]) => x.atan(),
+        ("atan2", [y, x]) => y.atan2(*x),
+        ("sinh", [x]) => x.sinh(),
+        ("cosh", [x]) => x.cosh(),
+        ("tanh", [x]) => x.tanh(),
+        ("floor", [x]) => x.floor(),
+        ("ceil", [x]) => x.ceil(),
+        ("round", [x]) => x.round(),
+        ("trunc", [x]) => x.trunc(),
+        ("pow", [x, y]) => x.powf(*y),
+        ("hypot", [x, y]) => x.hypot(*y),
+        ("min", [first, rest @ ..]) => rest.iter().fold(*first, |min, x| min.min(*x)),
+        ("max", [first, rest @ ..]) => rest.iter().fold(*first, |max, x| max.max(*x)),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn factorial(value: f64) -> Result<f64, CalcError> {
+    if value < 0.0 || value.fract() != 0.0 || value > 170.0 {
+        return Err(CalcError::NotInteger);
+    }
+    Ok((1..=value as u64).map(|n| n as f64).product())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Quantity {
+    value: f64,
+    unit: Option<Unit>,
+}
+
+impl Quantity {
+    fn number(value: f64) -> Self {
+        Self { value, unit: None }
+    }
+
+    fn unitless(&self) -> Result<f64, CalcError> {
+        match self.unit {
+            Some(_) => Err(CalcError::Units),
+            None => Ok(self.value),
+        }
+    }
+
+    fn convert(&self, unit: Unit) -> Result<Self, CalcError> {
+        match self.unit {
+            Some(from) if from.dimension == unit.dimension => {
+                let base = self.value * from.factor + from.offset;
+                Ok(Self {
+                    value: (base - unit.offset) / unit.factor,
+                    unit: Some(unit),
+                })
+            }
+            _ => Err(CalcError::Units),
+        }
+    }
+
+    /// Value of `other` in the unit of `self`, treating temperatures as differences
+    fn difference_in_unit(&self, other: &Self) -> Result<f64, CalcError> {
+        match (self.unit, other.unit) {
+            (None, None) => Ok(other.value),
+            (Some(unit), Some(other_unit)) if unit.dimension == other_unit.dimension => {
+                Ok(other.value * other_unit.factor / unit.factor)
+            }
+            _ => Err(CalcError::Units),
+        }
+    }
+
+    fn add(self, other: Self) -> Result<Self, CalcError> {
+        let value = self.value + self.difference_in_unit(&other)?;
+        Ok(Self { value, ..self })
+    }
+
+    fn sub(self, other: Self) -> Result<Self, CalcError> {
+        let value = self.value - self.difference_in_unit(&other)?;
+        Ok(Self { value, ..self })
+    }
+
+    fn mul(self, other: Self) -> Result<Self, CalcError> {
+        if self.unit.is_some() && other.unit.is_some() {
+            return Err(CalcError::Units);
+        }
+        Ok(Self {
+            value: self.value * other.value,
+            unit: self.unit.or(other.unit),
+        })
+    }
+
+    fn div(self, other: Self) -> Result<Self, CalcError> {
+        match (self.unit, other.unit) {
+            (_, None) => Ok(Self {
+                value: self.value / other.value,
+                ..self
+            }),
+            // A ratio of two quantities, e.g. `1 GiB / 1 MB`
+            (Some(_), Some(_)) => Ok(Self::number(self.value / self.difference_in_unit(&other)?)),
+            (None, Some(_)) => Err(CalcError::Units),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit)) {
+            let (number, length) = lex_number(&chars[i..])?;
+            tokens.push(Token::Number(number));
+            i += length;
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' || c == '°' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+        let token = match c {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 1;
+                Token::Op('^')
+            }
+            '+' | '-' | '*' | '/' | '^' | '%' | '!' => Token::Op(c),
+            '×' | '·' => Token::Op('*'),
+            '÷' => Token::Op('/'),
+            '−' => Token::Op('-'),
+            _ => return Err(CalcError::Syntax),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// Reads a number at the start of `chars`, returning it and the number of chars used
+fn lex_number(chars: &[char]) -> Result<(f64, usize), CalcError> {
+    let radix = match (chars.first(), chars.get(1)) {
+        (Some('0'), Some('x' | 'X')) => Some(16),
+        (Some('0'), Some('b' | 'B')) => Some(2),
+        (Some('0'), Some('o' | 'O')) => Some(8),
+        _ => None,
+    };
+    if let Some(radix) = radix {
+        let length = chars[2..]
+            .iter()
+            .take_while(|c| c.is_digit(radix) || **c == '_')
+            .count();
+        let digits: String = chars[2..2 + length].iter().filter(|c| **c != '_').collect();
+        let value = i64::from_str_radix(&digits, radix).map_err(|_| CalcError::Syntax)?;
+        return Ok((value as f64, 2 + length));
+    }
+
+    let mut text = String::new();
+    let mut length = 0;
+    let mut seen_dot = false;
+    while let Some(&c) = chars.get(length) {
+        match c {
+            '0'..='9' => text.push(c),
+            '_' => {}
+            '.' if !seen_dot => {
+                seen_dot = true;
+                text.push(c);
+            }
+            _ => break,
+        }
+        length += 1;
+    }
+    // Only an exponent when digits follow, so `2e` stays 2 * e
+    if matches!(chars.get(length), Some('e' | 'E')) {
+        let signed = matches!(chars.get(length + 1), Some('+' | '-'));
+        let digits_start = length + 1 + signed as usize;
+        if chars.get(digits_start).is_some_and(char::is_ascii_digit) {
+            text.push('e');
+            if signed {
+                text.push(chars[length + 1]);
+            }
+            length = digits_start;
+            while let Some(&c) = chars.get(length).filter(|c| c.is_ascii_digit()) {
+                text.push(c);
+                length += 1;
+            }
+        }
+    }
+    let value = text.parse::<f64>().map_err(|_| CalcError::Syntax)?;
+    Ok((value, length))
+}
+
+/// Recursive descent parser which evaluates while parsing
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    state: &'a CalculatorState,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(input: &str, state: &'a CalculatorState) -> Result<Quantity, CalcError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+            state,
+        };
+        let value = parser.expression()?;
+        if parser.position < parser.tokens.len() {
+            return Err(CalcError::Syntax);
+        }
+        Ok(value)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn eat_op(&mut self, op: char) -> bool {
+        self.eat(&Token::Op(op))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.eat(&Token::Ident(keyword.to_string()))
+    }
+
+    /// Whether the token at `position` can start an implicitly multiplied operand, as in `2pi`
+    fn starts_operand(&self, position: usize) -> bool {
+        match self.tokens.get(position) {
+            Some(Token::LParen) => true,
+            Some(Token::Ident(name)) => name != "of" && name != "mod",
+            _ => false,
+        }
+    }
+
+    fn expression(&mut self) -> Result<Quantity, CalcError> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat_op('+') {
+                value = value.add(self.term()?)?;
+            } else if self.eat_op('-') {
+                value = value.sub(self.term()?)?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Quantity, CalcError> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat_op('*') || self.eat_keyword("of") {
+                value = value.mul(self.unary()?)?;
+            } else if self.eat_op('/') {
+                value = value.div(self.unary()?)?;
+            } else if self.eat_op('%') || self.eat_keyword("mod") {
+                let divisor = self.unary()?.unitless()?;
+                value = Quantity::number(value.unitless()? % divisor);
+            } else if self.starts_operand(self.position) {
+                value = value.mul(self.power()?)?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Quantity, CalcError> {
+        if self.eat_op('-') {
+            let value = self.unary()?;
+            return Ok(Quantity {
+                value: -value.value,
+                ..value
+            });
+        }
+        if self.eat_op('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Quantity, CalcError> {
+        let base = self.postfix()?;
+        if self.eat_op('^') {
+            // Right-associative and binds tighter than a leading minus, so -2^2 is -4
+            let exponent = self.unary()?.unitless()?;
+            return Ok(Quantity::number(base.unitless()?.powf(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn postfix(&mut self) -> Result<Quantity, CalcError> {
+        let mut value = self.primary()?;
+        loop {
+            if self.eat_op('!') {
+                value = Quantity::number(factorial(value.unitless()?)?);
+            } else if self.peek() == Some(&Token::Op('%'))
+                && !matches!(self.tokens.get(self.position + 1), Some(Token::Number(_)))
+                && !self.starts_operand(self.position + 1)
+            {
+                // A percentage like `20% of 80`, otherwise `%` is the remainder
+                self.position += 1;
+                value = Quantity::number(value.unitless()? / 100.0);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Quantity, CalcError> {
+        match self.next() {
+            Some(Token::Number(value)) => {
+                let unit = match self.peek() {
+                    Some(Token::Ident(name)) => Unit::from_name(name),
+                    _ => None,
+                };
+                if unit.is_some() {
+                    self.position += 1;
+                }
+                Ok(Quantity { value, unit })
+            }
+            Some(Token::LParen) => {
+                let value = self.expression()?;
+                if !self.eat(&Token::RParen) {
+                    return Err(CalcError::Syntax);
+                }
+                Ok(value)
+            }
+            Some(Token::Ident(name)) => {
+                if self.eat(&Token::LParen) {
+                    let mut args = vec![];
+                    if !self.eat(&Token::RParen) {
+                        loop {
+                            args.push(self.expression()?.unitless()?);
+                            if self.eat(&Token::RParen) {
+                                break;
+                            }
+                            if !self.eat(&Token::Comma) {
+                                return Err(CalcError::Syntax);
+                            }
+                        }
+                    }
+                    return call(&name, &args)
+                        .map(Quantity::number)
+                        .ok_or(CalcError::UnknownName);
+                }
+                self.variable(&name)
+                    .map(Quantity::number)
+                    .ok_or(CalcError::UnknownName)
+            }
+            _ => Err(CalcError::Syntax),
+        }
+    }
+
+    fn variable(&self, name: &str) -> Option<f64> {
+        if name == "ans" {
+            return Some(self.state.ans);
+        }
+        self.state
+            .variables
+            .get(name)
+            .copied()
+            .or_else(|| constant(name))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Unit(Unit),
+    Radix(u32),
+}
+
+impl Target {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hex" | "hexadecimal" => Some(Self::Radix(16)),
+            "bin" | "binary" => Some(Self::Radix(2)),
+            "oct" | "octal" => Some(Self::Radix(8)),
+            "dec" | "decimal" => Some(Self::Radix(10)),
+            _ => Unit::from_name(name).map(Self::Unit),
+        }
+    }
+}
+
+enum Calculation {
+    Value(Quantity, Option<Target>),
+    Assignment(String, f64),
+}
+
+impl Calculation {
+    fn parse(query: &str, state: &CalculatorState) -> Result<Self, CalcError> {
+        if let Some((name, expression)) = query.split_once('=') {
+            let name = name.trim();
+            if !Self::is_assignable(name) {
+                return Err(CalcError::UnknownName);
+            }
+            let value = Parser::parse(expression, state)?.unitless()?;
+            return Ok(Self::Assignment(name.to_string(), value));
+        }
+        // `5 ft in in` has to split at the keyword, so search from the right
+        for keyword in [" to ", " in ", " as "] {
+            let Some((expression, target)) = query.rsplit_once(keyword) else {
+                continue;
+            };
+            let Some(target) = Target::from_name(target.trim()) else {
+                continue;
+            };
+            if let Ok(value) = Parser::parse(expression, state) {
+                return Ok(Self::Value(value, Some(target)));
+            }
+        }
+        Ok(Self::Value(Parser::parse(query, state)?, None))
+    }
+
+    fn is_assignable(name: &str) -> bool {
+        let mut chars = name.chars();
+        let starts_like_name = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_');
+        starts_like_name
+            && chars.all(|c| c.is_alphanumeric() || c == '_')
+            && name != "ans"
+            && constant(name).is_none()
+            && call(name, &[0.0]).is_none()
+    }
+}
+
+/// A single number or constant would otherwise show up for every query starting with one
+fn is_trivial(query: &str) -> bool {
+    let query = query.trim();
+    match tokenize(query).as_deref() {
+        Ok([Token::Number(_)]) => {
+            !query.starts_with("0x") && !query.starts_with("0b") && !query.starts_with("0o")
+        }
+        Ok([Token::Ident(name)]) => constant(name).is_some(),
+        _ => false,
+    }
+}
+
+fn format_decimal(value: f64, precision: usize) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+    if value.abs() >= 1e15 || value.abs() < 10f64.powi(-(precision as i32)) {
+        return format_scientific(value, precision);
+    }
+    let text = format!("{:.*}", precision, value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        return "0".to_string();
+    }
+    text.to_string()
+}
+
+fn format_scientific(value: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, value);
+    match text.split_once('e') {
+        Some((mantissa, exponent)) if mantissa.contains('.') => {
+            let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+            format!("{}e{}", mantissa, exponent)
+        }
+        _ => text,
+    }
+}
+
+fn format_radix(value: f64, radix: u32) -> Result<String, CalcError> {
+    if value.fract() != 0.0 || value.abs() >= 2f64.powi(53) {
+        return Err(CalcError::NotInteger);
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+    let value = value.abs() as u64;
+    Ok(match radix {
+        16 => format!("{}0x{:x}", sign, value),
+        8 => format!("{}0o{:o}", sign, value),
+        2 => format!("{}0b{:b}", sign, value),
+        _ => format!("{}{}", sign, value),
+    })
+}
+
+/// A formatted result and the number stored as `ans` when it is copied
+struct CalculatorResult {
+    text: String,
+    format: String,
+    value: f64,
+    score: i64,
+}
+
+pub struct CalculatorSource {
+    state: Arc<Mutex<CalculatorState>>,
+    helpers: Option<Arc<Helpers>>,
+    paste: bool,
+    precision: usize,
+}
+
+impl CalculatorSource {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(CalculatorState::default())),
+            helpers: None,
+            paste: false,
+            precision: _default_precision(),
+        }
+    }
+
+    fn results(
+        &self,
+        value: Quantity,
+        target: Option<Target>,
+    ) -> Result<Vec<CalculatorResult>, CalcError> {
+        let precision = self.precision;
+        let result = |text: String, format: &str, score: i64| CalculatorResult {
+            text,
+            format: format.to_string(),
+            value: value.value,
+            score,
+        };
+        match (target, value.unit) {
+            (Some(Target::Unit(unit)), _) => {
+                let converted = value.convert(unit)?;
+                let text = format!(
+                    "{} {}",
+                    format_decimal(converted.value, precision),
+                    unit.symbol
+                );
+                Ok(vec![CalculatorResult {
+                    value: converted.value,
+                    ..result(text, unit.symbol, 4)
+                }])
+            }
+            (Some(Target::Radix(radix)), None) => {
+                let text = format_radix(value.value, radix)?;
+                Ok(vec![result(text, Self::radix_name(radix), 4)])
+            }
+            (Some(Target::Radix(_)), Some(_)) => Err(CalcError::Units),
+            (None, Some(unit)) => {
+                let text = format!("{} {}", format_decimal(value.value, precision), unit.symbol);
+                Ok(vec![result(text, unit.symbol, 4)])
+            }
+            (None, None) => {
+                let decimal = format_decimal(value.value, precision);
+                let scientific = format_scientific(value.value, precision);
+                let show_scientific = value.value != 0.0
+                    && (value.value.abs() >= 1e6 || value.value.abs() < 1e-3)
+                    && scientific != decimal;
+                let mut results = vec![result(decimal, "decimal", 4)];
+                if let Ok(text) = format_radix(value.value, 16) {
+                    results.push(result(text, "hex", 3));
+                }
+                if let Ok(text) = format_radix(value.value, 2) {
+                    results.push(result(text, "binary", 1));
+                }
+                if show_scientific {
+                    results.push(result(scientific, "scientific", 2));
+                }
+                Ok(results)
+            }
+        }
+    }
+
+    fn radix_name(radix: u32) -> &'static str {
+        match radix {
+            16 => "hex",
+            8 => "octal",
+            2 => "binary",
+            _ => "decimal",
+        }
+    }
+
+    /// Changes the state and writes it to the cache
+    fn update_state(
+        state: &Mutex<CalculatorState>,
+        helpers: &Option<Arc<Helpers>>,
+        update: impl FnOnce(&mut CalculatorState),
+    ) {
+        let mut state = state.lock().unwrap();
+        update(&mut state);
+        if let Some(helpers) = helpers {
+            helpers.write_cache("calculator", &*state);
+        }
+    }
+
+    fn build_item(&self, query: &str, result: CalculatorResult) -> SearchItem {
+        let state = self.state.clone();
+        let helpers = self.helpers.clone();
+        let paste = self.paste;
+        let CalculatorResult {
+            text,
+            format,
+            value,
+            score,
+        } = result;
+        let action_text = text.clone();
+        SearchItem {
+            id: format!("{}{}", self.name(), format),
+            title: Some(text),
+            subtitle: Some(format!("{} ({})", query.trim(), format)),
+            icon: None,
+            image: None,
+            score,
+            source: self.name(),
+            layer: crate::model::ItemLayer::Top,
+            action: Rc::new(move |os| {
+                Self::update_state(&state, &helpers, |state| state.ans = value);
+                os.copy_and_paste(ClipboardContent::text(action_text.clone()), paste)
+            }),
+        }
+    }
+
+    fn build_assignment_item(&self, name: String, value: f64) -> SearchItem {
+        let state = self.state.clone();
+        let helpers = self.helpers.clone();
+        SearchItem {
+            id: format!("{}assignment", self.name()),
+            title: Some(format!(
+                "{} = {}",
+                name,
+                format_decimal(value, self.precision)
+            )),
+            subtitle: Some("Store variable".to_string()),
+            icon: None,
+            image: None,
+            score: 4,
+            source: self.name(),
+            layer: crate::model::ItemLayer::Top,
+            action: Rc::new(move |_os| {
+                let name = name.clone();
+                Self::update_state(&state, &helpers, |state| {
+                    state.variables.insert(name, value);
+                });
+                // Keep the launcher open to use the variable
+                false
+            }),
+        }
+    }
+}
+
+impl Source for CalculatorSource {
+    fn name(&self) -> &'static str {
+        "calculator"
+    }
+
+    fn init(&mut self, config: &toml::Table, helpers: Arc<Helpers>) {
+        let config: CalculatorConfig = config.clone().try_into().unwrap();
+        self.paste = config.paste;
+        self.precision = config.precision;
+        if let Some(state) = helpers.read_cache(self.name()) {
+            *self.state.lock().unwrap() = state;
+        }
+        self.helpers = Some(helpers);
+    }
+
+    fn deinit(&mut self) {}
+
+    fn search(
+        &self,
+        query: &str,
+        _matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
+    ) -> Vec<SearchItem> {
+        if query.trim().is_empty() || is_trivial(query) {
+            return vec![];
+        }
+        let calculation = {
+            let state = self.state.lock().unwrap();
+            Calculation::parse(query, &state)
+        };
+        match calculation {
+            Ok(Calculation::Assignment(name, value)) if value.is_finite() => {
+                vec![self.build_assignment_item(name, value)]
+            }
+            Ok(Calculation::Value(value, target)) if value.value.is_finite() => self
+                .results(value, target)
+                .map(|results| {
+                    results
+                        .into_iter()
+                        .map(|result| self.build_item(query, result))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+
+    /// Title of the best result, `None` if the query is not a calculation
+    fn calculate(source: &CalculatorSource, query: &str) -> Option<String> {
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        let mut items = source.search(query, &matcher);
+        items.sort_by_key(|item| std::cmp::Reverse(item.score));
+        items.into_iter().next().and_then(|item| item.title)
+    }
+
+    #[test]
+    fn calculates_expressions() {
+        let source = CalculatorSource::new();
+        let cases = [
+            ("1 + 2 * 3", "7"),
+            ("(1 + 2) * 3", "9"),
+            ("7 / 2", "3.5"),
+            ("1 / 3", "0.3333333333"),
+            ("10 % 4", "2"),
+            ("20% * 80", "16"),
+            ("20% of 80", "16"),
+            ("-2^2", "-4"),
+            ("2^3^2", "512"),
+            ("5!", "120"),
+            ("2pi", "6.2831853072"),
+            ("sqrt(16) + abs(-2)", "6"),
+            ("max(3, 9, 4)", "9"),
+            ("log(8, 2)", "3"),
+            ("0xff + 1", "256"),
+            ("0b101", "5"),
+            ("2^60", "1.1529215046e18"),
+            ("1e20 * 10", "1e21"),
+            ("255 in hex", "0xff"),
+            ("5 to binary", "0b101"),
+            ("1 km to m", "1000 m"),
+            ("5 ft in in", "60 in"),
+            ("100 C to F", "212 °F"),
+            ("1 GiB / 1 MB", "1073.741824"),
+            ("1 m + 20 cm", "1.2 m"),
+            ("3 kg * 2", "6 kg"),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(
+                calculate(&source, query).as_deref(),
+                Some(expected),
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_what_is_not_a_calculation() {
+        let source = CalculatorSource::new();
+        for query in [
+            "",
+            "42",
+            "pi",
+            "firefox",
+            "1 +",
+            "(1 + 2",
+            "1 m + 1 kg",
+            "2 m * 3 m",
+            "1.5 in hex",
+            "1 / 0",
+            "171!",
+            "unknown(2)",
+        ] {
+            assert_eq!(calculate(&source, query), None, "{}", query);
+        }
+    }
+
+    #[test]
+    fn assigns_variables() {
+        let mut state = CalculatorState::default();
+        state.variables.insert("x".to_string(), 4.0);
+        state.ans = 10.0;
+        let value = |query| match Calculation::parse(query, &state) {
+            Ok(Calculation::Value(value, None)) => Some(value.value),
+            _ => None,
+        };
+        assert_eq!(value("x * 2"), Some(8.0));
+        assert_eq!(value("ans + x"), Some(14.0));
+        assert!(matches!(
+            Calculation::parse("y = x + 1", &state),
+            Ok(Calculation::Assignment(name, value)) if name == "y" && value == 5.0
+        ));
+        for name in ["pi", "ans", "sqrt", "2x"] {
+            assert!(
+                Calculation::parse(&format!("{} = 1", name), &state).is_err(),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::clipboard_store::{ClipboardEntry, ClipboardStore};
+use crate::helpers::Helpers;
+use crate::model::{ClipboardContent, OSImage, SearchItem};
+use crate::source::Source;
+use fuzzy_matcher::FuzzyMatcher;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::rc::Rc;
+use std::sync::Arc;
+
+const fn _default_max_entries() -> usize {
+    1000
+}
+
+const fn _default_max_entry_size() -> usize {
+    // 5 MiB
+    5 * 1024 * 1024
+}
+
+const fn _default_interval() -> u64 {
+    500
+}
+
+const fn _default_icons() -> bool {
+    true
+}
+
+/// Shared by the source and `os clipboard-watch`, which records the entries
+#[derive(Deserialize)]
+pub struct ClipboardConfig {
+    /// Oldest entries are dropped beyond this count
+    #[serde(default = "_default_max_entries")]
+    pub max_entries: usize,
+    /// Content larger than this many bytes is not recorded
+    #[serde(default = "_default_max_entry_size")]
+    pub max_entry_size: usize,
+    /// Milliseconds between clipboard polls
+    #[serde(default = "_default_interval")]
+    pub interval: u64,
+    #[serde(default = "_default_icons")]
+    pub icons: bool,
+    /// Paste the entry into the focused window after copying
+    #[serde(default)]
+    pub paste: bool,
+}
+
+struct ClipboardItem {
+    hash: String,
+    /// Text entries are shown as is, images get a description like `Image 800×600 (png)`
+    title: String,
+    image: Option<OSImage>,
+    content: ClipboardContent,
+}
+
+impl ClipboardItem {
+    fn decode(entry: ClipboardEntry, icons: bool) -> Option<Self> {
+        let is_image = entry
+            .content
+            .mime_type
+            .as_ref()
+            .is_some_and(|mime_type| mime_type.starts_with("image/"));
+        if !is_image {
+            let title = String::from_utf8(entry.content.data.clone()).ok()?;
+            return Some(Self {
+                hash: entry.hash,
+                title,
+                image: None,
+                content: entry.content,
+            });
+        }
+        let image = Helpers::decode_image(entry.content.data.clone())?;
+        let format = entry
+            .content
+            .mime_type
+            .as_ref()
+            .and_then(|mime_type| mime_type.strip_prefix("image/"))
+            .unwrap_or("image");
+        let title = format!("Image {}×{} ({})", image.width(), image.height(), format);
+        Some(Self {
+            hash: entry.hash,
+            title,
+            image: if icons { Some(image) } else { None },
+            content: entry.content,
+        })
+    }
+}
+
+/// Lists the clipboard history recorded by `os clipboard-watch`
+pub struct ClipboardSource {
+    items: Vec<ClipboardItem>,
+    paste: bool,
+}
+
+impl ClipboardSource {
+    pub fn new() -> Self {
+        Self {
+            items: vec![],
+            paste: false,
+        }
+    }
+}
+
+impl Source for ClipboardSource {
+    fn name(&self) -> &'static str {
+        "clipboard"
+    }
+
+    fn init(&mut self, config: &toml::Table, _helpers: Arc<Helpers>) {
+        let config: ClipboardConfig = config.clone().try_into().unwrap();
+        self.paste = config.paste;
+        let entries = ClipboardStore::new().entries();
+        self.items = entries
+            .into_par_iter()
+            .filter_map(|entry| ClipboardItem::decode(entry, config.icons))
+            .collect();
+    }
+    fn deinit(&mut self) {}
+
+    fn search(
+        &self,
+        query: &str,
+        matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
+    ) -> Vec<SearchItem> {
+        let count = self.items.len() as i64;
+        self.items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                // Without a query the most recent entries come first
+                let score = if query.is_empty() {
+                    count - index as i64
+                } else {
+                    let match_text = item.title.chars().take(500).collect::<String>();
+                    matcher.fuzzy_match(&match_text, query).unwrap_or(0)
+                };
+                let content = item.content.clone();
+                let paste = self.paste;
+                SearchItem {
+                    id: format!("clipboard{}", item.hash),
+                    title: Some(item.title.clone()),
+                    subtitle: Some("(clipboard)".to_string()),
+                    icon: None,
+                    image: item.image.clone(),
+                    score,
+                    source: self.name(),
+                    action: Rc::new(move |os| os.copy_and_paste(content.clone(), paste)),
+                    layer: crate::model::ItemLayer::Bottom,
+                }
+            })
+            .collect()
+    }
+}
@@ -1,23 +1,26 @@
 use crate::helpers::Helpers;
-use crate::model::ImmutablePixbuf;
-use crate::model::{ClipboardContent, SearchItem};
+use crate::model::{ClipboardContent, OSImage, SearchItem};
+use crate::pins::Pins;
 use crate::source::Source;
-use ahash::{AHasher, RandomState};
+use ahash::RandomState;
 use fuzzy_matcher::FuzzyMatcher;
 use nut::{DBBuilder, DB};
 use rayon::prelude::*;
-use relm4::gtk::gdk::Texture;
-use relm4::gtk::gdk_pixbuf::Pixbuf;
-use relm4::gtk::glib::Bytes;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::hash::{BuildHasher, Hasher};
-use std::io::Cursor;
-use std::path::PathBuf;
-use std::process::Command;
-use std::sync::Arc;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use xdg::BaseDirectories;
 
+/// Query prefixes switching from copying to managing entries
+const PIN_PREFIX: &str = ":pin";
+const DELETE_PREFIX: &str = ":delete";
+const WIPE_PREFIX: &str = ":wipe";
+
 const fn _default_icons() -> bool {
     true
 }
@@ -26,63 +29,279 @@ const fn _default_icons() -> bool {
 pub struct CliphistConfig {
     #[serde(default = "_default_icons")]
     pub icons: bool,
+    /// Paste the entry into the focused window after copying
+    #[serde(default)]
+    pub paste: bool,
+    /// cliphist database, defaults to `$XDG_CACHE_HOME/cliphist/db`
+    pub db_path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Copy,
+    Pin,
+    Delete,
+    Wipe,
+}
+
+impl Mode {
+    /// Splits a management prefix off the query
+    fn parse(query: &str) -> (Self, &str) {
+        for (prefix, mode) in [
+            (PIN_PREFIX, Self::Pin),
+            (DELETE_PREFIX, Self::Delete),
+            (WIPE_PREFIX, Self::Wipe),
+        ] {
+            if let Some(rest) = query.strip_prefix(prefix) {
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return (mode, rest.trim_start());
+                }
+            }
+        }
+        (Self::Copy, query)
+    }
+}
+
+#[derive(Clone)]
+struct CliphistEntry {
+    /// Key of the entry in the cliphist database, used by `cliphist delete`
+    id: u64,
+    /// Text entries are shown as is, images get a description like `Image 800×600 (png)`
+    title: String,
+    image: Option<OSImage>,
+    content: ClipboardContent,
+    hash: u64,
+}
+
+impl CliphistEntry {
+    fn decode(id: u64, value: Vec<u8>, icons: bool, random_state: &RandomState) -> Option<Self> {
+        let mut hasher = random_state.build_hasher();
+        hasher.write(&value);
+        let hash = hasher.finish();
+        let Ok(format) = image::guess_format(&value) else {
+            let title = String::from_utf8(value).ok()?;
+            return Some(Self {
+                id,
+                content: ClipboardContent::text(title.clone()),
+                title,
+                image: None,
+                hash,
+            });
+        };
+        let mime_type = format.to_mime_type().to_string();
+        let image = Helpers::decode_image(value.clone())?;
+        let title = format!(
+            "Image {}×{} ({})",
+            image.width(),
+            image.height(),
+            format.extensions_str().first().unwrap_or(&"image")
+        );
+        Some(Self {
+            id,
+            title,
+            image: if icons { Some(image) } else { None },
+            content: ClipboardContent::new(value, Some(mime_type)),
+            hash,
+        })
+    }
+
+    fn pin_id(&self) -> String {
+        format!("cliphist{}", self.hash)
+    }
+}
+
+/// Deletes entries through cliphist, which reads `<id>\t<preview>` lines like its `list` output
+fn cliphist_delete(entries: &[CliphistEntry]) -> std::io::Result<()> {
+    let mut child = Command::new("cliphist")
+        .arg("delete")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    for entry in entries {
+        writeln!(stdin, "{}\t", entry.id)?;
+    }
+    drop(stdin);
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!(
+            "cliphist delete failed with {}",
+            status
+        )));
+    }
+    Ok(())
 }
 
 pub struct CliphistSource {
-    items: Vec<(
-        Option<String>,
-        Option<ImmutablePixbuf>,
-        ClipboardContent,
-        u64,
-    )>,
+    entries: Arc<Mutex<Vec<CliphistEntry>>>,
+    pins: Arc<Mutex<Pins>>,
+    paste: bool,
 }
 
 impl CliphistSource {
     pub fn new() -> CliphistSource {
-        CliphistSource { items: vec![] }
+        CliphistSource {
+            entries: Arc::new(Mutex::new(vec![])),
+            pins: Arc::new(Mutex::new(Pins::new())),
+            paste: false,
+        }
     }
 
-    fn db(db_path: PathBuf) -> nut::DB {
-        let db = DBBuilder::new(db_path).read_only(true).build().unwrap();
-        db
+    fn db(db_path: &Path) -> Result<DB, nut::Error> {
+        DBBuilder::new(db_path).read_only(true).build()
     }
+
+    /// Reads and decodes all entries of a cliphist database
+    fn load_entries(db_path: &Path, icons: bool) -> Vec<CliphistEntry> {
+        let db = match Self::db(db_path) {
+            Ok(db) => db,
+            Err(err) => {
+                log::warn!("Failed to open {}: {:?}", db_path.display(), err);
+                return vec![];
+            }
+        };
+        let mut values = vec![];
+        if let Ok(tx) = db.begin_tx() {
+            if let Ok(bucket) = tx.bucket(b"b") {
+                let _ = bucket.for_each::<nut::Error>(Box::new(|key, value| {
+                    let id = key.try_into().map(u64::from_be_bytes);
+                    if let (Ok(id), Some(value)) = (id, value) {
+                        values.push((id, value.to_vec()));
+                    }
+                    Ok(())
+                }));
+            }
+        }
+        let random_state = RandomState::with_seed(42);
+        values
+            .into_par_iter()
+            .filter_map(|(id, value)| CliphistEntry::decode(id, value, icons, &random_state))
+            .collect()
+    }
+
+    fn score(
+        entry: &CliphistEntry,
+        query: &str,
+        matcher: &(dyn FuzzyMatcher + Send + Sync),
+    ) -> i64 {
+        if query.is_empty() {
+            return 1;
+        }
+        let match_text = entry.title.chars().take(500).collect::<String>();
+        matcher.fuzzy_match(&match_text, query).unwrap_or(0)
+    }
+
     fn build_item(
         &self,
-        item: (
-            Option<String>,
-            Option<ImmutablePixbuf>,
-            ClipboardContent,
-            u64,
-        ),
+        entry: &CliphistEntry,
+        mode: Mode,
         query: &str,
-        matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
+        pinned: bool,
+        score: i64,
     ) -> SearchItem {
-        let score = if query.is_empty() {
-            1
-        } else if item.0.is_some() {
-            let match_text = item.0.as_ref().unwrap();
-            let match_text = match_text.chars().take(500).collect::<String>();
-            matcher.fuzzy_match(&match_text, &query).unwrap_or(0)
-        } else {
-            // TODO: better default score for images
-            1
+        let paste = self.paste;
+        let entries = self.entries.clone();
+        let pins = self.pins.clone();
+        let action_entry = entry.clone();
+        let action_query = query.to_string();
+        let action: Rc<dyn Fn(&mut crate::os::Os) -> bool> = match mode {
+            Mode::Copy | Mode::Wipe => {
+                Rc::new(move |os| os.copy_and_paste(action_entry.content.clone(), paste))
+            }
+            // Managing entries keeps the launcher open in the same mode
+            Mode::Pin => Rc::new(move |os| {
+                let result = pins.lock().unwrap().toggle(&action_entry.pin_id());
+                os.report(result.map(|_| ()));
+                os.set_query(action_query.clone());
+                false
+            }),
+            Mode::Delete => Rc::new(move |os| {
+                Self::delete(&entries, &pins, std::slice::from_ref(&action_entry), os);
+                os.set_query(action_query.clone());
+                false
+            }),
+        };
+        let subtitle = match (mode, pinned) {
+            (Mode::Pin, true) => "Unpin",
+            (Mode::Pin, false) => "Pin",
+            (Mode::Delete, _) => "Delete from cliphist",
+            (_, true) => "(cliphist, pinned)",
+            (_, false) => "(cliphist)",
         };
+        SearchItem {
+            id: format!("cliphist{}", entry.hash),
+            title: Some(entry.title.clone()),
+            subtitle: Some(subtitle.to_string()),
+            icon: None,
+            image: entry.image.clone(),
+            // Pinned entries sort above all others in the layer
+            score: if pinned { score + 1000 } else { score },
+            source: self.name(),
+            action,
+            layer: crate::model::ItemLayer::Bottom,
+        }
+    }
 
+    fn build_wipe_item(
+        &self,
+        matching: Vec<CliphistEntry>,
+        query: &str,
+        filter: &str,
+    ) -> SearchItem {
+        let entries = self.entries.clone();
+        let pins = self.pins.clone();
+        let action_query = query.to_string();
         SearchItem {
-            id: "cliphist".to_string() + &item.3.to_string(),
-            title: item.0,
+            id: "cliphist:wipe".to_string(),
+            title: Some(format!(
+                "Delete {} entries matching '{}'",
+                matching.len(),
+                filter
+            )),
             subtitle: Some("(cliphist)".to_string()),
             icon: None,
-            image: item.1,
-            score,
+            image: None,
+            score: 1,
             source: self.name(),
-            action: Box::new(move |os| {
-                let clipboard_content = item.2.clone();
-                os.copy_to_clipboard(clipboard_content);
-                true
+            action: Rc::new(move |os| {
+                Self::delete(&entries, &pins, &matching, os);
+                os.set_query(action_query.clone());
+                false
             }),
-            layer: crate::model::ItemLayer::Bottom,
+            layer: crate::model::ItemLayer::Top,
+        }
+    }
+
+    /// Deletes entries through cliphist and drops them from the results and pins
+    fn delete(
+        entries: &Mutex<Vec<CliphistEntry>>,
+        pins: &Mutex<Pins>,
+        deleted: &[CliphistEntry],
+        os: &mut crate::os::Os,
+    ) {
+        let result = cliphist_delete(deleted);
+        if os.report(result) {
+            let result = Self::forget(entries, pins, deleted);
+            os.report(result);
+        }
+    }
+
+    /// Drops deleted entries from the results and pins
+    fn forget(
+        entries: &Mutex<Vec<CliphistEntry>>,
+        pins: &Mutex<Pins>,
+        deleted: &[CliphistEntry],
+    ) -> rusqlite::Result<()> {
+        let ids: HashSet<u64> = deleted.iter().map(|entry| entry.id).collect();
+        entries
+            .lock()
+            .unwrap()
+            .retain(|entry| !ids.contains(&entry.id));
+        let pins = pins.lock().unwrap();
+        for entry in deleted {
+            pins.remove(&entry.pin_id())?;
         }
+        Ok(())
     }
 }
 
@@ -91,49 +310,14 @@ impl Source for CliphistSource {
         "cliphist"
     }
 
-    fn init(&mut self, config: &toml::Table, helpers: Arc<Helpers>) {
+    fn init(&mut self, config: &toml::Table, _helpers: Arc<Helpers>) {
         let config: CliphistConfig = config.clone().try_into().unwrap();
-        let xdg = BaseDirectories::with_prefix("cliphist").unwrap();
-        let db_path = xdg.place_cache_file("db").unwrap();
-        let seed = 42;
-        let random_state = RandomState::with_seed(seed);
-        let db = Self::db(db_path);
-        let tx = db.begin_tx().unwrap();
-
-        let bucket = tx.bucket(b"b").unwrap();
-        let mut values = vec![];
-        let _ = bucket.for_each::<nut::Error>(Box::new(|_key, value| {
-            let value = value.unwrap();
-            values.push(value.to_vec());
-            Ok(())
-        }));
-        let items = values
-            .into_par_iter()
-            .filter_map(|value| {
-                let mut hasher = random_state.build_hasher();
-                hasher.write(&value);
-                let cursor_value = value.to_vec();
-                let clipboard_content = ClipboardContent(cursor_value.to_vec());
-                let cursor = Cursor::new(cursor_value);
-                let hash = hasher.finish();
-                let (title, image) = match Pixbuf::from_read(cursor) {
-                    Err(_) => (String::from_utf8(value).ok(), None),
-                    Ok(img) => (
-                        None,
-                        if config.icons {
-                            Some(ImmutablePixbuf::new(img))
-                        } else {
-                            None
-                        },
-                    ),
-                };
-                if title.is_none() && image.is_none() {
-                    return None;
-                }
-                Some((title, image, clipboard_content, hash))
-            })
-            .collect();
-        self.items = items;
+        self.paste = config.paste;
+        let db_path = config.db_path.unwrap_or_else(|| {
+            let xdg = BaseDirectories::with_prefix("cliphist").unwrap();
+            xdg.get_cache_home().join("db")
+        });
+        *self.entries.lock().unwrap() = Self::load_entries(&db_path, config.icons);
     }
     fn deinit(&mut self) {}
 
@@ -142,9 +326,212 @@ impl Source for CliphistSource {
         query: &str,
         matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
     ) -> Vec<SearchItem> {
-        self.items
-            .par_iter()
-            .map(|item| self.build_item(item.clone(), query, matcher))
+        let (mode, filter) = Mode::parse(query);
+        let pinned = self.pins.lock().unwrap().ids();
+        let entries = self.entries.lock().unwrap();
+        let scored = entries
+            .iter()
+            .map(|entry| (entry, Self::score(entry, filter, matcher.as_ref())));
+        let mut items: Vec<SearchItem> = vec![];
+        if mode == Mode::Wipe {
+            let matching: Vec<CliphistEntry> = scored
+                .clone()
+                .filter(|(_, score)| *score > 0)
+                .map(|(entry, _)| entry.clone())
+                .collect();
+            if !filter.is_empty() && !matching.is_empty() {
+                items.push(self.build_wipe_item(matching, query, filter));
+            }
+        }
+        items.extend(scored.map(|(entry, score)| {
+            let is_pinned = pinned.contains(&entry.pin_id());
+            self.build_item(entry, mode, query, is_pinned, score)
+        }));
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opts::Config;
+    use crate::os::Os;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 255]))
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    /// Writes a database laid out like cliphist's, entries in bucket `b` by big-endian id
+    fn write_db(path: &Path, values: Vec<(u64, Vec<u8>)>) {
+        let mut db = DBBuilder::new(path).build().unwrap();
+        let mut tx = db.begin_rw_tx().unwrap();
+        {
+            let mut bucket = tx.create_bucket(b"b").unwrap();
+            for (id, value) in values {
+                bucket.put(&id.to_be_bytes(), value).unwrap();
+            }
+        }
+        tx.commit().unwrap();
+    }
+
+    fn source(dir: &tempfile::TempDir, icons: bool) -> CliphistSource {
+        let db_path = dir.path().join("db");
+        write_db(
+            &db_path,
+            vec![
+                (1, b"hello world".to_vec()),
+                (2, png(3, 2)),
+                (3, b"hello again".to_vec()),
+                // Neither text nor an image
+                (4, vec![0xff, 0xfe, 0x00]),
+            ],
+        );
+        let mut entries = CliphistSource::load_entries(&db_path, icons);
+        entries.sort_by_key(|entry| entry.id);
+        CliphistSource {
+            entries: Arc::new(Mutex::new(entries)),
+            pins: Arc::new(Mutex::new(Pins::open(&dir.path().join("pins.db")))),
+            paste: false,
+        }
+    }
+
+    /// Titles and subtitles of the matching items, highest score first
+    fn search(source: &CliphistSource, query: &str) -> Vec<(String, String)> {
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        let mut items = source.search(query, &matcher);
+        items.retain(|item| item.score > 0);
+        items.sort_by_key(|item| std::cmp::Reverse(item.score));
+        items
+            .into_iter()
+            .map(|item| (item.title.unwrap(), item.subtitle.unwrap()))
             .collect()
     }
+
+    fn item(title: &str, subtitle: &str) -> (String, String) {
+        (title.to_string(), subtitle.to_string())
+    }
+
+    fn entry(source: &CliphistSource, id: u64) -> CliphistEntry {
+        let entries = source.entries.lock().unwrap();
+        entries.iter().find(|entry| entry.id == id).unwrap().clone()
+    }
+
+    #[test]
+    fn decodes_text_and_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&dir, true);
+        let entries = source.entries.lock().unwrap();
+        let titles: Vec<_> = entries.iter().map(|entry| entry.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["hello world", "Image 3×2 (png)", "hello again"]
+        );
+        assert_eq!(entries[0].content.data, b"hello world");
+
+        let image = &entries[1];
+        assert_eq!(image.content.mime_type.as_deref(), Some("image/png"));
+        assert_eq!(image.content.data, png(3, 2));
+        let preview = image.image.as_ref().unwrap();
+        assert_eq!((preview.width(), preview.height()), (3, 2));
+    }
+
+    #[test]
+    fn images_have_no_preview_without_icons() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&dir, false);
+        let image = entry(&source, 2);
+        assert_eq!(image.title, "Image 3×2 (png)");
+        assert!(image.image.is_none());
+    }
+
+    #[test]
+    fn pin_mode_toggles_pins() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&dir, false);
+        assert_eq!(
+            search(&source, ":pin world"),
+            vec![item("hello world", "Pin")]
+        );
+
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        let selected = source
+            .search(":pin again", &matcher)
+            .into_iter()
+            .find(|item| item.title.as_deref() == Some("hello again"))
+            .unwrap();
+        let mut os = Os::with_data_dir(Config::default(), dir.path());
+        assert!(!os.select(&selected));
+        // The launcher stays in pin mode
+        assert_eq!(os.take_query().as_deref(), Some(":pin again"));
+        assert!(os.take_error().is_none());
+        assert_eq!(search(&source, ":pin")[0], item("hello again", "Unpin"));
+        assert_eq!(
+            search(&source, "")[0],
+            item("hello again", "(cliphist, pinned)")
+        );
+
+        let pin_id = entry(&source, 3).pin_id();
+        assert!(!source.pins.lock().unwrap().toggle(&pin_id).unwrap());
+        assert_eq!(
+            search(&source, "again"),
+            vec![item("hello again", "(cliphist)")]
+        );
+    }
+
+    #[test]
+    fn delete_mode_forgets_deleted_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&dir, false);
+        assert_eq!(
+            search(&source, ":delete world"),
+            vec![item("hello world", "Delete from cliphist")]
+        );
+        // Only a whole word switches modes
+        assert_eq!(search(&source, ":deleteworld"), vec![]);
+
+        let deleted = entry(&source, 1);
+        source
+            .pins
+            .lock()
+            .unwrap()
+            .toggle(&deleted.pin_id())
+            .unwrap();
+        CliphistSource::forget(
+            &source.entries,
+            &source.pins,
+            std::slice::from_ref(&deleted),
+        )
+        .unwrap();
+        assert_eq!(search(&source, ":delete world"), vec![]);
+        assert!(!source
+            .pins
+            .lock()
+            .unwrap()
+            .ids()
+            .contains(&deleted.pin_id()));
+        assert_eq!(source.entries.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn wipe_mode_offers_to_delete_all_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&dir, false);
+        let items = search(&source, ":wipe hello");
+        assert!(items.contains(&item("Delete 2 entries matching 'hello'", "(cliphist)")));
+        // The matches themselves can still be copied
+        assert!(items.contains(&item("hello world", "(cliphist)")));
+        assert_eq!(items.len(), 3);
+
+        assert_eq!(search(&source, ":wipe nothing like it"), vec![]);
+        assert!(!search(&source, ":wipe")
+            .iter()
+            .any(|(title, _)| title.starts_with("Delete")));
+    }
 }
@@ -0,0 +1,429 @@
+use crate::helpers::Helpers;
+use crate::model::{ClipboardContent, SearchItem};
+use crate::source::Source;
+use crate::APP_NAME;
+use fuzzy_matcher::FuzzyMatcher;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use xdg::BaseDirectories;
+
+fn _default_fetch_url() -> String {
+    "https://api.frankfurter.app/latest".to_string()
+}
+
+fn _default_cache_duration() -> Duration {
+    // 12 hours
+    Duration::from_secs(60 * 60 * 12)
+}
+
+/// Exchange rates relative to `base`, e.g. `{"base": "EUR", "rates": {"USD": 1.08}}`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RateTable {
+    pub base: String,
+    /// Day the rates are from, shown next to results
+    #[serde(default)]
+    pub date: Option<String>,
+    pub rates: HashMap<String, f64>,
+}
+
+impl RateTable {
+    /// Reads a JSON table, or a TOML table for files ending in `.toml`
+    fn read(path: &Path) -> Option<Self> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                warn!("Failed to read rates from {}: {}", path.display(), err);
+                return None;
+            }
+        };
+        let table = if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            toml::from_str::<Self>(&content).map_err(|err| err.to_string())
+        } else {
+            serde_json::from_str::<Self>(&content).map_err(|err| err.to_string())
+        };
+        match table {
+            Ok(table) => Some(table),
+            Err(err) => {
+                warn!("Invalid rates in {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    fn rate(&self, currency: &str) -> Option<f64> {
+        if currency == self.base.to_uppercase() {
+            return Some(1.0);
+        }
+        self.rates
+            .iter()
+            .find(|(code, _)| code.to_uppercase() == currency)
+            .map(|(_, rate)| *rate)
+    }
+
+    fn convert(&self, amount: f64, from: &str, to: &str) -> Option<f64> {
+        Some(amount / self.rate(from)? * self.rate(to)?)
+    }
+}
+
+/// Refreshes the rate table, e.g. from a web API
+pub trait RateFetcher: Send {
+    fn fetch(&self) -> Result<RateTable, String>;
+}
+
+/// Fetches rates from an API answering in the `RateTable` format, like frankfurter.app
+#[cfg(feature = "currency-fetch")]
+pub struct HttpRateFetcher {
+    url: String,
+}
+
+#[cfg(feature = "currency-fetch")]
+impl RateFetcher for HttpRateFetcher {
+    fn fetch(&self) -> Result<RateTable, String> {
+        ureq::get(&self.url)
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CurrencyConfig {
+    /// Rate table to read, defaults to `rates.json` or `rates.toml` in the data dir
+    pub rates_file: Option<PathBuf>,
+    /// Refresh the rates over HTTP, needs the `currency-fetch` feature
+    #[serde(default)]
+    pub fetch: bool,
+    #[serde(default = "_default_fetch_url")]
+    pub fetch_url: String,
+    #[serde(default = "_default_cache_duration")]
+    pub cache_duration: Duration,
+    /// Paste the result into the focused window after copying
+    #[serde(default)]
+    pub paste: bool,
+}
+
+struct CurrencySourceInner {
+    rates: Mutex<Option<RateTable>>,
+    fetching: AtomicBool,
+}
+
+pub struct CurrencySource {
+    inner: Arc<CurrencySourceInner>,
+    paste: bool,
+}
+
+impl CurrencySource {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(CurrencySourceInner {
+                rates: Mutex::new(None),
+                fetching: AtomicBool::new(false),
+            }),
+            paste: false,
+        }
+    }
+
+    fn default_rates_file() -> Option<PathBuf> {
+        let xdg = BaseDirectories::with_prefix(APP_NAME).ok()?;
+        xdg.find_data_file("rates.json")
+            .or_else(|| xdg.find_data_file("rates.toml"))
+    }
+
+    fn fetcher(config: &CurrencyConfig) -> Option<Box<dyn RateFetcher>> {
+        if !config.fetch {
+            return None;
+        }
+        #[cfg(feature = "currency-fetch")]
+        return Some(Box::new(HttpRateFetcher {
+            url: config.fetch_url.clone(),
+        }));
+        #[cfg(not(feature = "currency-fetch"))]
+        {
+            warn!(
+                "Fetching rates from {} needs the 'currency-fetch' feature, using the rates file",
+                config.fetch_url
+            );
+            None
+        }
+    }
+
+    /// Fetches in a separate thread and caches the result
+    fn refresh(&self, fetcher: Box<dyn RateFetcher>, helpers: Arc<Helpers>) {
+        let inner = self.inner.clone();
+        let name = self.name();
+        inner.fetching.store(true, Ordering::SeqCst);
+        std::thread::spawn(move || {
+            match fetcher.fetch() {
+                Ok(rates) => {
+                    helpers.write_cache(name, &rates);
+                    *inner.rates.lock().unwrap() = Some(rates);
+                }
+                Err(err) => warn!("Failed to fetch exchange rates: {}", err),
+            }
+            inner.fetching.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Splits `100usd`, `100 usd`, `$100` or `usd` into an amount and a currency code
+    fn parse_amount(text: &str) -> Option<(f64, String)> {
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let symbol = |c: char| match c {
+            '$' => Some("USD"),
+            '€' => Some("EUR"),
+            '£' => Some("GBP"),
+            '¥' => Some("JPY"),
+            '₹' => Some("INR"),
+            _ => None,
+        };
+        let (number, currency) = if let Some(code) = text.chars().next().and_then(symbol) {
+            (text.chars().skip(1).collect::<String>(), code.to_string())
+        } else {
+            let split = text.find(|c: char| c.is_alphabetic() || symbol(c).is_some())?;
+            let (number, currency) = text.split_at(split);
+            let mut chars = currency.chars();
+            let currency = match chars.next().and_then(symbol) {
+                Some(code) if chars.next().is_none() => code.to_string(),
+                _ => currency.to_uppercase(),
+            };
+            (number.to_string(), currency)
+        };
+        let number = number.replace(['_', ','], "");
+        let amount = if number.is_empty() {
+            1.0
+        } else {
+            number.parse().ok()?
+        };
+        Some((amount, currency))
+    }
+
+    /// Parses `100 usd in eur` or `usd to eur`
+    fn parse_query(query: &str) -> Option<(f64, String, String)> {
+        let words: Vec<&str> = query.split_whitespace().collect();
+        let keyword = words
+            .iter()
+            .rposition(|word| matches!(word.to_lowercase().as_str(), "in" | "to"))?;
+        if keyword == 0 || keyword + 2 != words.len() {
+            return None;
+        }
+        let (amount, from) = Self::parse_amount(&words[..keyword].join(" "))?;
+        let (_, to) = Self::parse_amount(words[keyword + 1])?;
+        Some((amount, from, to))
+    }
+
+    fn format_amount(amount: f64) -> String {
+        // Small amounts like rates of weak currencies need more digits
+        let precision = if amount.abs() >= 1.0 || amount == 0.0 {
+            2
+        } else {
+            6
+        };
+        let text = format!("{:.*}", precision, amount);
+        if precision > 2 {
+            return text.trim_end_matches('0').to_string();
+        }
+        text
+    }
+}
+
+impl Source for CurrencySource {
+    fn name(&self) -> &'static str {
+        "currency"
+    }
+
+    fn init(&mut self, config: &toml::Table, helpers: Arc<Helpers>) {
+        let config: CurrencyConfig = config.clone().try_into().unwrap();
+        self.paste = config.paste;
+        let rates_file = config.rates_file.clone().or_else(Self::default_rates_file);
+        let mut rates = rates_file.and_then(|path| RateTable::read(&path));
+        if let Some(fetcher) = Self::fetcher(&config) {
+            // Fetched rates are newer than the file, even when they have to be refreshed
+            if let Some(cached) = helpers.read_cache(self.name()) {
+                rates = Some(cached);
+            }
+            if helpers.cache_expired(self.name(), config.cache_duration) {
+                self.refresh(fetcher, helpers.clone());
+            }
+        }
+        if let Some(rates) = rates {
+            self.inner.rates.lock().unwrap().get_or_insert(rates);
+        }
+    }
+
+    fn loading(&self) -> bool {
+        self.inner.fetching.load(Ordering::SeqCst)
+    }
+
+    fn deinit(&mut self) {}
+
+    fn search(
+        &self,
+        query: &str,
+        _matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
+    ) -> Vec<SearchItem> {
+        let Some((amount, from, to)) = Self::parse_query(query) else {
+            return vec![];
+        };
+        let rates = self.inner.rates.lock().unwrap();
+        let Some(rates) = rates.as_ref() else {
+            return vec![];
+        };
+        let Some(converted) = rates.convert(amount, &from, &to) else {
+            return vec![];
+        };
+        let converted_text = Self::format_amount(converted);
+        let date = rates
+            .date
+            .as_ref()
+            .map(|date| format!(", rates from {}", date))
+            .unwrap_or_default();
+        let paste = self.paste;
+        let action_text = converted_text.clone();
+        vec![SearchItem {
+            id: format!("{}{}{}", self.name(), from, to),
+            title: Some(format!("{} {}", converted_text, to)),
+            subtitle: Some(format!("{} {}{}", Self::format_amount(amount), from, date)),
+            icon: None,
+            image: None,
+            score: 1,
+            source: self.name(),
+            layer: crate::model::ItemLayer::Top,
+            // Copies only the number, so it can be pasted into forms
+            action: Rc::new(move |os| {
+                os.copy_and_paste(ClipboardContent::text(action_text.clone()), paste)
+            }),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+    use std::time::Instant;
+
+    const JSON_RATES: &str =
+        r#"{"base": "EUR", "date": "2024-01-02", "rates": {"USD": 1.25, "GBP": 0.8}}"#;
+
+    const TOML_RATES: &str = r#"
+        base = "EUR"
+        [rates]
+        USD = 1.25
+        jpy = 160.0
+    "#;
+
+    /// Hands out a fixed table, standing in for the web API
+    struct FixedRates(RateTable);
+
+    impl RateFetcher for FixedRates {
+        fn fetch(&self) -> Result<RateTable, String> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn write_rates(dir: &tempfile::TempDir, file: &str, content: &str) -> PathBuf {
+        let path = dir.path().join(file);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Title and subtitle of the conversion
+    fn convert(source: &CurrencySource, query: &str) -> Option<(String, String)> {
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        let item = source.search(query, &matcher).into_iter().next()?;
+        Some((item.title?, item.subtitle?))
+    }
+
+    #[test]
+    fn reads_json_and_toml_rate_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let json = RateTable::read(&write_rates(&dir, "rates.json", JSON_RATES)).unwrap();
+        assert_eq!(json.base, "EUR");
+        assert_eq!(json.date.as_deref(), Some("2024-01-02"));
+        assert_eq!(json.rate("GBP"), Some(0.8));
+
+        let toml = RateTable::read(&write_rates(&dir, "rates.toml", TOML_RATES)).unwrap();
+        assert_eq!(toml.date, None);
+        assert_eq!(toml.rate("EUR"), Some(1.0));
+        // Codes are matched case-insensitively
+        assert_eq!(toml.rate("JPY"), Some(160.0));
+
+        // TOML in a file without the extension is read as JSON
+        assert!(RateTable::read(&write_rates(&dir, "rates", TOML_RATES)).is_none());
+        assert!(RateTable::read(&dir.path().join("missing.json")).is_none());
+    }
+
+    #[test]
+    fn converts_between_currencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let rates = RateTable::read(&write_rates(&dir, "rates.json", JSON_RATES)).unwrap();
+        assert_eq!(rates.convert(10.0, "EUR", "USD"), Some(12.5));
+        assert_eq!(rates.convert(12.5, "USD", "EUR"), Some(10.0));
+        assert_eq!(
+            rates
+                .convert(1.0, "USD", "GBP")
+                .map(CurrencySource::format_amount),
+            Some("0.64".to_string())
+        );
+        assert_eq!(rates.convert(1.0, "USD", "CHF"), None);
+
+        let config: toml::Table = toml::from_str(&format!(
+            "rates_file = {:?}",
+            dir.path().join("rates.json").to_str().unwrap()
+        ))
+        .unwrap();
+        let mut source = CurrencySource::new();
+        source.init(
+            &config,
+            Arc::new(Helpers::with_cache_dir(dir.path().to_path_buf())),
+        );
+        assert_eq!(
+            convert(&source, "100 usd in eur"),
+            Some((
+                "80.00 EUR".to_string(),
+                "100.00 USD, rates from 2024-01-02".to_string()
+            ))
+        );
+        assert_eq!(
+            convert(&source, "$2 to £").map(|(title, _)| title),
+            Some("1.28 GBP".to_string())
+        );
+        assert_eq!(
+            convert(&source, "eur to gbp").map(|(title, _)| title),
+            Some("0.8 GBP".to_string())
+        );
+        assert_eq!(convert(&source, "100 usd in chf"), None);
+        assert_eq!(convert(&source, "100 usd"), None);
+    }
+
+    #[test]
+    fn fetched_rates_are_used_and_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let helpers = Arc::new(Helpers::with_cache_dir(dir.path().to_path_buf()));
+        let fetched: RateTable = toml::from_str(TOML_RATES).unwrap();
+        let source = CurrencySource::new();
+        source.refresh(Box::new(FixedRates(fetched)), helpers.clone());
+
+        let started = Instant::now();
+        while source.loading() {
+            assert!(started.elapsed() < Duration::from_secs(5), "still loading");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            convert(&source, "1 eur in jpy").map(|(title, _)| title),
+            Some("160.00 JPY".to_string())
+        );
+        let cached: RateTable = helpers.read_cache(source.name()).unwrap();
+        assert_eq!(cached.rate("USD"), Some(1.25));
+    }
+}
@@ -0,0 +1,734 @@
+use crate::helpers::Helpers;
+use crate::model::{ClipboardContent, SearchItem};
+use crate::source::Source;
+use chrono::{
+    DateTime, Days, FixedOffset, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta,
+    TimeZone, Utc,
+};
+use chrono_tz::{Tz, TZ_VARIANTS};
+use fuzzy_matcher::FuzzyMatcher;
+use serde::Deserialize;
+use std::rc::Rc;
+use std::sync::Arc;
+
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y"];
+
+#[derive(Deserialize)]
+pub struct DateTimeConfig {
+    /// Paste the result into the focused window after copying
+    #[serde(default)]
+    pub paste: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Zone {
+    Local,
+    Named(Tz),
+    /// A fixed offset like `PST` or `UTC+2`, with the name it was given as
+    Fixed(FixedOffset, String),
+}
+
+impl Zone {
+    /// Parses abbreviations, offsets, IANA names and city names like `new york`
+    fn parse(name: &str) -> Option<Self> {
+        let lowercase = name.trim().to_lowercase();
+        match lowercase.as_str() {
+            "" => return None,
+            "local" => return Some(Self::Local),
+            "utc" | "gmt" | "z" => return Some(Self::Named(Tz::UTC)),
+            _ => {}
+        }
+        if let Some(hours) = Self::abbreviation_offset(&lowercase) {
+            let offset = FixedOffset::east_opt((hours * 3600.0) as i32)?;
+            return Some(Self::Fixed(offset, name.trim().to_uppercase()));
+        }
+        if let Some(offset) = Self::parse_offset(&lowercase) {
+            return Some(Self::Fixed(offset, format!("UTC{}", offset)));
+        }
+        let wanted = lowercase.replace(' ', "_");
+        let by_name = TZ_VARIANTS
+            .iter()
+            .find(|tz| tz.name().to_lowercase() == wanted);
+        let by_city = || {
+            TZ_VARIANTS.iter().find(|tz| {
+                tz.name()
+                    .rsplit('/')
+                    .next()
+                    .is_some_and(|city| city.to_lowercase() == wanted)
+            })
+        };
+        by_name.or_else(by_city).map(|tz| Self::Named(*tz))
+    }
+
+    fn abbreviation_offset(abbreviation: &str) -> Option<f64> {
+        let hours = match abbreviation {
+            "hst" => -10.0,
+            "akst" => -9.0,
+            "akdt" | "pst" => -8.0,
+            "pdt" | "mst" => -7.0,
+            "mdt" | "cst" => -6.0,
+            "cdt" | "est" => -5.0,
+            "edt" => -4.0,
+            "wet" => 0.0,
+            "west" | "bst" | "cet" => 1.0,
+            "cest" | "eet" => 2.0,
+            "eest" | "msk" => 3.0,
+            "ist" => 5.5,
+            "jst" | "kst" => 9.0,
+            "aest" => 10.0,
+            "aedt" => 11.0,
+            "nzst" => 12.0,
+            "nzdt" => 13.0,
+            _ => return None,
+        };
+        Some(hours)
+    }
+
+    /// Parses `+02:00`, `-0530`, `utc+2` or `gmt-5`
+    fn parse_offset(text: &str) -> Option<FixedOffset> {
+        let text = text
+            .strip_prefix("utc")
+            .or_else(|| text.strip_prefix("gmt"))
+            .unwrap_or(text);
+        let sign = match text.chars().next()? {
+            '+' => 1,
+            '-' => -1,
+            _ => return None,
+        };
+        let text = &text[1..];
+        let (hours, minutes) = match text.split_once(':') {
+            Some((hours, minutes)) => (hours, minutes),
+            None if text.len() == 4 => (text.get(..2)?, text.get(2..)?),
+            None => (text, "0"),
+        };
+        let hours: i32 = hours.parse().ok()?;
+        let minutes: i32 = minutes.parse().ok()?;
+        if hours > 14 || minutes >= 60 {
+            return None;
+        }
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+    }
+
+    fn localize(&self, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+        let instant = match self {
+            Self::Local => Local.from_local_datetime(&naive).earliest()?.to_utc(),
+            Self::Named(tz) => tz.from_local_datetime(&naive).earliest()?.to_utc(),
+            Self::Fixed(offset, _) => offset.from_local_datetime(&naive).earliest()?.to_utc(),
+        };
+        Some(instant)
+    }
+
+    fn naive(&self, instant: &DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Self::Local => instant.with_timezone(&Local).naive_local(),
+            Self::Named(tz) => instant.with_timezone(tz).naive_local(),
+            Self::Fixed(offset, _) => instant.with_timezone(offset).naive_local(),
+        }
+    }
+
+    fn format(&self, instant: &DateTime<Utc>, format: &str) -> String {
+        match self {
+            Self::Local => instant.with_timezone(&Local).format(format).to_string(),
+            Self::Named(tz) => instant.with_timezone(tz).format(format).to_string(),
+            Self::Fixed(offset, _) => instant.with_timezone(offset).format(format).to_string(),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Self::Local => "Local time".to_string(),
+            Self::Named(tz) => tz.name().to_string(),
+            Self::Fixed(_, name) => name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Span {
+    Months(i64),
+    Days(i64),
+    Seconds(i64),
+}
+
+#[derive(Debug, Clone)]
+struct Moment {
+    instant: DateTime<Utc>,
+    zone: Zone,
+}
+
+impl Moment {
+    /// Shifts by a span, months and days follow the calendar of the moment's zone
+    fn shift(&self, span: Span, sign: i64) -> Option<Self> {
+        let naive = self.zone.naive(&self.instant);
+        let instant = match span {
+            Span::Seconds(seconds) => self
+                .instant
+                .checked_add_signed(TimeDelta::try_seconds(seconds * sign)?)?,
+            Span::Days(days) => {
+                let days = Days::new((days * sign).unsigned_abs());
+                let shifted = if sign > 0 {
+                    naive.checked_add_days(days)
+                } else {
+                    naive.checked_sub_days(days)
+                };
+                self.zone.localize(shifted?)?
+            }
+            Span::Months(months) => {
+                let months = Months::new(u32::try_from((months * sign).unsigned_abs()).ok()?);
+                let shifted = if sign > 0 {
+                    naive.checked_add_months(months)
+                } else {
+                    naive.checked_sub_months(months)
+                };
+                self.zone.localize(shifted?)?
+            }
+        };
+        Some(Self {
+            instant,
+            zone: self.zone.clone(),
+        })
+    }
+}
+
+enum Calculation {
+    Moment(Moment),
+    Duration(TimeDelta),
+}
+
+enum Target {
+    Zone(Zone),
+    Unix,
+}
+
+/// Parses a clock time like `15:00`, `3pm` or `3:30 pm`, returning it and the number of words used
+fn parse_time(words: &[&str]) -> Option<(NaiveTime, usize)> {
+    let word = words.first()?.to_lowercase();
+    match word.as_str() {
+        "noon" => return Some((NaiveTime::from_hms_opt(12, 0, 0)?, 1)),
+        "midnight" => return Some((NaiveTime::MIN, 1)),
+        _ => {}
+    }
+    let next = words.get(1).map(|word| word.to_lowercase());
+    let (clock, pm, used) = if let Some(clock) = word.strip_suffix("am") {
+        (clock, Some(false), 1)
+    } else if let Some(clock) = word.strip_suffix("pm") {
+        (clock, Some(true), 1)
+    } else {
+        match next.as_deref() {
+            Some("am") => (word.as_str(), Some(false), 2),
+            Some("pm") => (word.as_str(), Some(true), 2),
+            _ => (word.as_str(), None, 1),
+        }
+    };
+    let parts: Vec<&str> = clock.split(':').collect();
+    // A bare number is not a time without am or pm
+    if pm.is_none() && parts.len() < 2 {
+        return None;
+    }
+    let numbers = parts
+        .iter()
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    let (mut hour, minute, second) = match numbers[..] {
+        [hour] => (hour, 0, 0),
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => return None,
+    };
+    if let Some(pm) = pm {
+        if hour == 0 || hour > 12 {
+            return None;
+        }
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    Some((NaiveTime::from_hms_opt(hour, minute, second)?, used))
+}
+
+/// Parses seconds or, for 13 digit values, milliseconds since the epoch
+fn parse_timestamp(text: &str) -> Option<Moment> {
+    let timestamp: i64 = text.parse().ok()?;
+    let instant = if timestamp.abs() >= 100_000_000_000 {
+        DateTime::from_timestamp_millis(timestamp)?
+    } else {
+        DateTime::from_timestamp(timestamp, 0)?
+    };
+    Some(Moment {
+        instant,
+        zone: Zone::Local,
+    })
+}
+
+fn parse_moment(words: &[&str], now: DateTime<Utc>) -> Option<Moment> {
+    let first = words.first()?.to_lowercase();
+    let local = |instant| Moment {
+        instant,
+        zone: Zone::Local,
+    };
+    match (first.as_str(), words.len()) {
+        ("now" | "time", 1) => return Some(local(now)),
+        ("today" | "tomorrow" | "yesterday", 1) => {
+            let today = Zone::Local.naive(&now).date();
+            let date = match first.as_str() {
+                "tomorrow" => today.succ_opt()?,
+                "yesterday" => today.pred_opt()?,
+                _ => today,
+            };
+            return Some(local(Zone::Local.localize(date.and_time(NaiveTime::MIN))?));
+        }
+        ("unix" | "timestamp", 2) => return parse_timestamp(words[1]),
+        _ => {}
+    }
+    if words.len() == 1 {
+        if let Some(timestamp) = first.strip_prefix('@') {
+            return parse_timestamp(timestamp);
+        }
+        if let Ok(instant) = DateTime::parse_from_rfc3339(words[0]) {
+            let offset = *instant.offset();
+            return Some(Moment {
+                instant: instant.to_utc(),
+                zone: Zone::Fixed(offset, format!("UTC{}", offset)),
+            });
+        }
+    }
+
+    let mut rest = words;
+    let date = DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(rest[0], format).ok());
+    if date.is_some() {
+        rest = &rest[1..];
+    }
+    let time = parse_time(rest).map(|(time, used)| {
+        rest = &rest[used..];
+        time
+    });
+    if date.is_none() && time.is_none() {
+        return None;
+    }
+    let zone = if rest.is_empty() {
+        Zone::Local
+    } else {
+        Zone::parse(&rest.join(" "))?
+    };
+    // A time without a date is today in its own zone
+    let date = date.unwrap_or_else(|| zone.naive(&now).date());
+    let instant = zone.localize(date.and_time(time.unwrap_or(NaiveTime::MIN)))?;
+    Some(Moment { instant, zone })
+}
+
+/// Parses durations like `90 days`, `2 weeks 3 days` or `90d`
+fn parse_spans(words: &[&str]) -> Option<Vec<Span>> {
+    let mut spans = vec![];
+    let mut index = 0;
+    while index < words.len() {
+        let word = words[index].to_lowercase();
+        let split = word
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(word.len());
+        let (number, unit) = if split == word.len() {
+            index += 2;
+            (word.clone(), words.get(index - 1)?.to_lowercase())
+        } else {
+            index += 1;
+            (word[..split].to_string(), word[split..].to_string())
+        };
+        let number: i64 = number.parse().ok()?;
+        // Spans too large to calculate with are not a date calculation
+        let span = match unit.as_str() {
+            "s" | "sec" | "secs" | "second" | "seconds" => Span::Seconds(number),
+            "m" | "min" | "mins" | "minute" | "minutes" => Span::Seconds(number.checked_mul(60)?),
+            "h" | "hr" | "hrs" | "hour" | "hours" => Span::Seconds(number.checked_mul(60 * 60)?),
+            "d" | "day" | "days" => Span::Days(number),
+            "w" | "wk" | "week" | "weeks" => Span::Days(number.checked_mul(7)?),
+            "mo" | "month" | "months" => Span::Months(number),
+            "y" | "yr" | "year" | "years" => Span::Months(number.checked_mul(12)?),
+            _ => return None,
+        };
+        spans.push(span);
+    }
+    if spans.is_empty() {
+        return None;
+    }
+    Some(spans)
+}
+
+/// Evaluates `moment (+|- duration)*`, `moment - moment`, `until moment` and `since moment`
+fn parse_expression(words: &[&str], now: DateTime<Utc>) -> Option<Calculation> {
+    match words.first()?.to_lowercase().as_str() {
+        "until" => {
+            let moment = parse_moment(&words[1..], now)?;
+            return Some(Calculation::Duration(moment.instant - now));
+        }
+        "since" => {
+            let moment = parse_moment(&words[1..], now)?;
+            return Some(Calculation::Duration(now - moment.instant));
+        }
+        _ => {}
+    }
+    let mut segments: Vec<(i64, &[&str])> = vec![];
+    let mut sign = 1;
+    let mut start = 0;
+    for (index, word) in words.iter().enumerate() {
+        if *word == "+" || *word == "-" {
+            segments.push((sign, &words[start..index]));
+            sign = if *word == "+" { 1 } else { -1 };
+            start = index + 1;
+        }
+    }
+    segments.push((sign, &words[start..]));
+
+    let mut moment = parse_moment(segments[0].1, now)?;
+    if let [_, (-1, other)] = segments[..] {
+        if let Some(other) = parse_moment(other, now) {
+            return Some(Calculation::Duration(moment.instant - other.instant));
+        }
+    }
+    for (sign, words) in &segments[1..] {
+        for span in parse_spans(words)? {
+            moment = moment.shift(span, *sign)?;
+        }
+    }
+    Some(Calculation::Moment(moment))
+}
+
+/// Parses the whole query, an optional `in <zone>`, `to <zone>` or `to unix` converts the result
+fn parse_query(query: &str, now: DateTime<Utc>) -> Option<(Calculation, Option<Target>)> {
+    let words: Vec<&str> = query.split_whitespace().collect();
+    let keyword = words
+        .iter()
+        .rposition(|word| matches!(word.to_lowercase().as_str(), "in" | "to"));
+    if let Some(index) = keyword.filter(|index| *index > 0 && index + 1 < words.len()) {
+        let (expression, target_words) = (&words[..index], &words[index + 1..]);
+        let target_name = target_words.join(" ").to_lowercase();
+        let target = match target_name.as_str() {
+            "unix" | "timestamp" | "epoch" => Some(Target::Unix),
+            _ => Zone::parse(&target_name).map(Target::Zone),
+        };
+        if let Some(target) = target {
+            if let Some(calculation) = parse_expression(expression, now) {
+                return Some((calculation, Some(target)));
+            }
+        } else if let (Some(from), Some(to)) = (
+            parse_moment(expression, now),
+            parse_moment(target_words, now),
+        ) {
+            // `2024-01-01 to 2024-03-01`
+            return Some((Calculation::Duration(to.instant - from.instant), None));
+        }
+    }
+    parse_expression(&words, now).map(|calculation| (calculation, None))
+}
+
+fn format_duration(duration: TimeDelta) -> String {
+    let seconds = duration.num_seconds().unsigned_abs();
+    let parts: Vec<String> = [
+        (seconds / 86400, "day"),
+        (seconds % 86400 / 3600, "hour"),
+        (seconds % 3600 / 60, "minute"),
+        (seconds % 60, "second"),
+    ]
+    .iter()
+    .filter(|(count, _)| *count > 0)
+    .map(|(count, unit)| format!("{} {}{}", count, unit, if *count == 1 { "" } else { "s" }))
+    .collect();
+    if parts.is_empty() {
+        return "0 seconds".to_string();
+    }
+    let sign = if duration < TimeDelta::zero() {
+        "-"
+    } else {
+        ""
+    };
+    format!("{}{}", sign, parts.join(", "))
+}
+
+fn format_total(value: f64, unit: &str) -> String {
+    let text = format!("{:.2}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    format!("{} {}", text, unit)
+}
+
+/// Like "in 3 days, 2 hours" or "5 minutes ago", empty when within a minute
+fn relative(instant: &DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let difference = *instant - now;
+    if difference.num_seconds().abs() < 60 {
+        return String::new();
+    }
+    // Seconds only add noise here, and two units are precise enough
+    let rounded = TimeDelta::minutes(difference.num_minutes().abs());
+    let text = format_duration(rounded)
+        .split(", ")
+        .take(2)
+        .collect::<Vec<&str>>()
+        .join(", ");
+    if difference > TimeDelta::zero() {
+        format!(", in {}", text)
+    } else {
+        format!(", {} ago", text)
+    }
+}
+
+pub struct DateTimeSource {
+    paste: bool,
+}
+
+impl DateTimeSource {
+    pub fn new() -> Self {
+        Self { paste: false }
+    }
+
+    /// Returns (title, subtitle, kind) of each result
+    fn results(
+        calculation: Calculation,
+        target: Option<Target>,
+        now: DateTime<Utc>,
+    ) -> Vec<(String, String, &'static str)> {
+        match (calculation, target) {
+            (Calculation::Moment(moment), Some(Target::Unix)) => {
+                vec![(
+                    moment.instant.timestamp().to_string(),
+                    "Unix timestamp".to_string(),
+                    "unix",
+                )]
+            }
+            (Calculation::Moment(moment), target) => {
+                let zone = match target {
+                    Some(Target::Zone(zone)) => zone,
+                    _ => moment.zone.clone(),
+                };
+                let instant = &moment.instant;
+                let mut results = vec![
+                    (
+                        zone.format(instant, "%a %Y-%m-%d %H:%M:%S %:z"),
+                        format!("{}{}", zone.label(), relative(instant, now)),
+                        "datetime",
+                    ),
+                    (
+                        zone.format(instant, "%Y-%m-%dT%H:%M:%S%:z"),
+                        "ISO 8601".to_string(),
+                        "iso",
+                    ),
+                    (
+                        instant.timestamp().to_string(),
+                        "Unix timestamp".to_string(),
+                        "unix",
+                    ),
+                ];
+                // Show times given in another zone in local time as well
+                if zone != Zone::Local {
+                    results.insert(
+                        1,
+                        (
+                            Zone::Local.format(instant, "%a %Y-%m-%d %H:%M:%S %:z"),
+                            Zone::Local.label(),
+                            "local",
+                        ),
+                    );
+                }
+                results
+            }
+            (Calculation::Duration(duration), _) => {
+                let seconds = duration.num_seconds() as f64;
+                let mut results = vec![(
+                    format_duration(duration),
+                    "Duration".to_string(),
+                    "duration",
+                )];
+                let days = format_total(seconds / 86400.0, "days");
+                if days != results[0].0 {
+                    results.push((days, "Duration in days".to_string(), "days"));
+                }
+                results.push((
+                    format_total(seconds / 3600.0, "hours"),
+                    "Duration in hours".to_string(),
+                    "hours",
+                ));
+                results.push((
+                    format!("{} seconds", duration.num_seconds()),
+                    "Duration in seconds".to_string(),
+                    "seconds",
+                ));
+                results
+            }
+        }
+    }
+}
+
+impl Source for DateTimeSource {
+    fn name(&self) -> &'static str {
+        "datetime"
+    }
+
+    fn init(&mut self, config: &toml::Table, _helpers: Arc<Helpers>) {
+        let config: DateTimeConfig = config.clone().try_into().unwrap();
+        self.paste = config.paste;
+    }
+
+    fn deinit(&mut self) {}
+
+    fn search(
+        &self,
+        query: &str,
+        _matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
+    ) -> Vec<SearchItem> {
+        let now = Utc::now();
+        let Some((calculation, target)) = parse_query(query, now) else {
+            return vec![];
+        };
+        let results = Self::results(calculation, target, now);
+        let count = results.len() as i64;
+        results
+            .into_iter()
+            .enumerate()
+            .map(|(index, (title, subtitle, kind))| {
+                let paste = self.paste;
+                let text = title.clone();
+                SearchItem {
+                    id: format!("{}{}", self.name(), kind),
+                    title: Some(title),
+                    subtitle: Some(subtitle),
+                    icon: None,
+                    image: None,
+                    // Keep the order of the results
+                    score: count - index as i64,
+                    source: self.name(),
+                    layer: crate::model::ItemLayer::Top,
+                    action: Rc::new(move |os| {
+                        os.copy_and_paste(ClipboardContent::text(text.clone()), paste)
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-01-31T12:00:00Z")
+            .unwrap()
+            .to_utc()
+    }
+
+    fn moment(query: &str) -> Option<DateTime<Utc>> {
+        match parse_query(query, now())? {
+            (Calculation::Moment(moment), _) => Some(moment.instant),
+            (Calculation::Duration(_), _) => None,
+        }
+    }
+
+    #[test]
+    fn parses_offsets() {
+        let offset = |text| Zone::parse_offset(text).map(|offset| offset.local_minus_utc());
+        assert_eq!(offset("+0530"), Some(5 * 3600 + 30 * 60));
+        assert_eq!(offset("utc-3"), Some(-3 * 3600));
+        assert_eq!(offset("+02:00"), Some(2 * 3600));
+        assert_eq!(offset("+15"), None);
+        // Four bytes, but not four characters
+        assert_eq!(offset("+a€"), None);
+    }
+
+    #[test]
+    fn shifts_by_spans() {
+        let expected = |text| DateTime::parse_from_rfc3339(text).unwrap().to_utc();
+        assert_eq!(moment("now + 90m"), Some(expected("2024-01-31T13:30:00Z")));
+        assert_eq!(
+            moment("now - 1 day 2 hours"),
+            Some(expected("2024-01-30T10:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn huge_spans_are_not_calculated() {
+        assert!(moment("now + 9223372036854775807 hours").is_none());
+        assert!(moment("now + 9223372036854775807 years").is_none());
+        assert!(moment("now + 9223372036854775807 weeks").is_none());
+        assert!(moment("now + 9223372036854775807 seconds").is_none());
+    }
+
+    /// Title of the result of `kind` for `query`
+    fn result(query: &str, kind: &str) -> Option<String> {
+        let (calculation, target) = parse_query(query, now())?;
+        DateTimeSource::results(calculation, target, now())
+            .into_iter()
+            .find(|(_, _, result_kind)| *result_kind == kind)
+            .map(|(title, _, _)| title)
+    }
+
+    #[test]
+    fn calculates_dates_and_durations() {
+        let cases = [
+            (
+                "15:00 PST in Berlin",
+                "datetime",
+                "Thu 2024-02-01 00:00:00 +01:00",
+            ),
+            ("15:00 PST in Berlin", "unix", "1706742000"),
+            ("3pm utc+2 to new york", "iso", "2024-01-31T08:00:00-05:00"),
+            ("now + 90m in UTC", "iso", "2024-01-31T13:30:00+00:00"),
+            (
+                "2024-01-31 12:00 UTC + 90 days",
+                "iso",
+                "2024-04-30T12:00:00+00:00",
+            ),
+            (
+                "unix 1700000000 in UTC",
+                "datetime",
+                "Tue 2023-11-14 22:13:20 +00:00",
+            ),
+            ("unix 1700000000", "unix", "1700000000"),
+            ("@1700000000000 in UTC", "iso", "2023-11-14T22:13:20+00:00"),
+            ("2024-01-01 12:00 UTC to unix", "unix", "1704110400"),
+            ("2024-01-01 UTC to 2024-03-01 UTC", "duration", "60 days"),
+            ("2024-01-01 UTC to 2024-03-01 UTC", "hours", "1440 hours"),
+            (
+                "2024-03-01 12:00 UTC - 2024-01-01 UTC",
+                "duration",
+                "60 days, 12 hours",
+            ),
+            (
+                "2024-01-01 UTC - 2024-01-02 06:00 UTC",
+                "days",
+                "-1.25 days",
+            ),
+            (
+                "until 2024-02-01 13:30:15 UTC",
+                "duration",
+                "1 day, 1 hour, 30 minutes, 15 seconds",
+            ),
+            ("since 2024-01-31 11:59 UTC", "seconds", "60 seconds"),
+        ];
+        for (query, kind, expected) in cases {
+            assert_eq!(result(query, kind).as_deref(), Some(expected), "{}", query);
+        }
+    }
+
+    #[test]
+    fn shifts_now_by_days_in_the_local_calendar() {
+        // A day may have 23 or 25 hours when the local zone changes to or from DST
+        let hours = (moment("now + 90 days").unwrap() - now()).num_hours();
+        assert!((90 * 24 - 1..=90 * 24 + 1).contains(&hours), "{}", hours);
+    }
+
+    #[test]
+    fn ignores_what_is_not_a_time() {
+        let source = DateTimeSource::new();
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> =
+            Box::new(fuzzy_matcher::skim::SkimMatcherV2::default());
+        for query in [
+            "",
+            "firefox",
+            "42",
+            "1 + 2",
+            "10 km to m",
+            "2 + 2 in hex",
+            "open in firefox",
+            "now + 5 apples",
+            "2024-13-45",
+        ] {
+            assert!(source.search(query, &matcher).is_empty(), "{}", query);
+        }
+    }
+}
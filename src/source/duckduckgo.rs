@@ -1,19 +1,18 @@
+use super::paging::{Page, PagedResults, PagedSearches};
 use super::Source;
 use crate::helpers::Helpers;
-use rayon::prelude::*;
+use crate::model::{ClipboardContent, ItemLayer, SearchItem};
+use log::warn;
 use scraper::{Html, Selector};
 use ureq::get;
 
 use serde::{Deserialize, Serialize};
 
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
-use std::{
-    borrow::Cow,
-    fs,
-    path::{Path, PathBuf},
-};
-use urlencoding;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
 const fn _default_icons() -> bool {
     true
@@ -27,12 +26,51 @@ fn _default_user_agent() -> String {
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:109.0) Gecko/20100101 Firefox/118.0".to_string()
 }
 
-#[derive(Deserialize)]
+const fn _default_suggestions() -> bool {
+    true
+}
+
+const fn _default_instant_answers() -> bool {
+    true
+}
+
+const fn _default_debounce() -> u64 {
+    300
+}
+
+fn _default_html_url() -> String {
+    "https://html.duckduckgo.com/html/".to_string()
+}
+
+fn _default_autocomplete_url() -> String {
+    "https://duckduckgo.com/ac/".to_string()
+}
+
+fn _default_instant_answer_url() -> String {
+    "https://api.duckduckgo.com/".to_string()
+}
+
+#[derive(Deserialize, Clone)]
 pub struct DuckduckgoConfig {
     #[serde(default = "_default_cache_duration")]
     pub cache_duration: Duration,
     #[serde(default = "_default_user_agent")]
     pub user_agent: String,
+    /// Suggest queries while typing
+    #[serde(default = "_default_suggestions")]
+    pub suggestions: bool,
+    /// Show abstracts, definitions and answers above the results
+    #[serde(default = "_default_instant_answers")]
+    pub instant_answers: bool,
+    /// Milliseconds without typing before suggestions are fetched
+    #[serde(default = "_default_debounce")]
+    pub debounce: u64,
+    #[serde(default = "_default_html_url")]
+    pub html_url: String,
+    #[serde(default = "_default_autocomplete_url")]
+    pub autocomplete_url: String,
+    #[serde(default = "_default_instant_answer_url")]
+    pub instant_answer_url: String,
 }
 
 #[derive(Clone)]
@@ -41,24 +79,409 @@ struct SearchResult {
     url: String,
 }
 
-#[derive(Clone)]
-enum DuckduckgoSearchState {
-    None,
-    Searched(Vec<SearchResult>),
+/// Hidden fields of the "Next" form, posted to get the next page
+type NextPageForm = Vec<(String, String)>;
+
+/// Answer of the instant answer API, only the fields shown as items
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct InstantAnswerResponse {
+    #[serde(rename = "Heading")]
+    heading: String,
+    #[serde(rename = "AbstractText")]
+    abstract_text: String,
+    #[serde(rename = "AbstractURL")]
+    abstract_url: String,
+    #[serde(rename = "AbstractSource")]
+    abstract_source: String,
+    /// Usually text, but calculations and conversions answer with objects
+    #[serde(rename = "Answer")]
+    answer: serde_json::Value,
+    #[serde(rename = "Definition")]
+    definition: String,
+    #[serde(rename = "DefinitionURL")]
+    definition_url: String,
+    #[serde(rename = "DefinitionSource")]
+    definition_source: String,
+}
+
+/// An instant answer item, opening `url` or copying the title if there is none
+#[derive(Serialize, Deserialize, Clone)]
+struct InstantAnswer {
+    title: String,
+    subtitle: String,
+    url: String,
+}
+
+/// Suggestions and instant answers of one query
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct QueryAnswers {
+    suggestions: Vec<String>,
+    instant_answers: Vec<InstantAnswer>,
+    /// Unix timestamp, answers older than the cache duration are fetched again
+    fetched_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct DuckduckgoCache {
+    queries: HashMap<String, QueryAnswers>,
+}
+
+struct DuckduckgoSourceInner {
+    searches: Arc<PagedSearches<SearchResult, NextPageForm>>,
+    answers: Mutex<HashMap<String, QueryAnswers>>,
+    /// Latest typed query and when it was typed, picked up by the fetching thread
+    pending: Mutex<Option<(String, Instant)>>,
+    wakeup: Condvar,
+    fetching_answers: AtomicBool,
 }
 
 pub struct DuckduckgoSource {
-    state: Arc<RwLock<DuckduckgoSearchState>>,
+    inner: Arc<DuckduckgoSourceInner>,
     config: Option<DuckduckgoConfig>,
 }
 
 impl DuckduckgoSource {
     pub fn new() -> Self {
         Self {
-            state: Arc::new(RwLock::new(DuckduckgoSearchState::None)),
+            inner: Arc::new(DuckduckgoSourceInner {
+                searches: Arc::new(PagedSearches::new()),
+                answers: Mutex::new(HashMap::new()),
+                pending: Mutex::new(None),
+                wakeup: Condvar::new(),
+                fetching_answers: AtomicBool::new(false),
+            }),
             config: None,
         }
     }
+
+    fn fetch_suggestions(config: &DuckduckgoConfig, query: &str) -> Result<Vec<String>, String> {
+        let url = format!(
+            "{}?q={}&type=list",
+            config.autocomplete_url,
+            urlencoding::encode(query)
+        );
+        let (_, suggestions): (String, Vec<String>) = get(&url)
+            .set("User-Agent", &config.user_agent)
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())?;
+        Ok(suggestions)
+    }
+
+    fn fetch_instant_answers(
+        config: &DuckduckgoConfig,
+        query: &str,
+    ) -> Result<Vec<InstantAnswer>, String> {
+        let url = format!(
+            "{}?q={}&format=json&no_html=1&skip_disambig=1",
+            config.instant_answer_url,
+            urlencoding::encode(query)
+        );
+        let response: InstantAnswerResponse = get(&url)
+            .set("User-Agent", &config.user_agent)
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())?;
+        let mut answers = vec![];
+        if let Some(answer) = response.answer.as_str().filter(|answer| !answer.is_empty()) {
+            answers.push(InstantAnswer {
+                title: answer.to_string(),
+                subtitle: "Answer (DuckDuckGo)".to_string(),
+                url: String::new(),
+            });
+        }
+        if !response.abstract_text.is_empty() {
+            answers.push(InstantAnswer {
+                title: response.heading.clone(),
+                subtitle: format!("{} ({})", response.abstract_text, response.abstract_source),
+                url: response.abstract_url,
+            });
+        }
+        if !response.definition.is_empty() {
+            answers.push(InstantAnswer {
+                title: response.definition,
+                subtitle: format!("Definition ({})", response.definition_source),
+                url: response.definition_url,
+            });
+        }
+        Ok(answers)
+    }
+
+    /// Fetches suggestions and instant answers of the latest query once typing paused.
+    /// Runs for the lifetime of the launcher
+    fn run_fetcher(
+        inner: Arc<DuckduckgoSourceInner>,
+        config: DuckduckgoConfig,
+        helpers: Arc<Helpers>,
+    ) {
+        let debounce = Duration::from_millis(config.debounce);
+        loop {
+            let mut pending = inner.pending.lock().unwrap();
+            while pending.is_none() {
+                pending = inner.wakeup.wait(pending).unwrap();
+            }
+            let (query, typed_at) = pending.clone().unwrap();
+            let wait = debounce.saturating_sub(typed_at.elapsed());
+            if !wait.is_zero() {
+                // The query may change while waiting, check again afterwards
+                drop(pending);
+                std::thread::sleep(wait);
+                continue;
+            }
+            *pending = None;
+            inner.fetching_answers.store(true, Ordering::SeqCst);
+            drop(pending);
+
+            let mut answers = QueryAnswers {
+                fetched_at: chrono::Utc::now().timestamp(),
+                ..Default::default()
+            };
+            if config.suggestions {
+                match Self::fetch_suggestions(&config, &query) {
+                    Ok(suggestions) => answers.suggestions = suggestions,
+                    Err(err) => warn!("Failed to fetch DuckDuckGo suggestions: {}", err),
+                }
+            }
+            if config.instant_answers {
+                match Self::fetch_instant_answers(&config, &query) {
+                    Ok(instant_answers) => answers.instant_answers = instant_answers,
+                    Err(err) => warn!("Failed to fetch DuckDuckGo instant answers: {}", err),
+                }
+            }
+            let mut cached = inner.answers.lock().unwrap();
+            // Drop expired queries so the cache does not grow forever
+            let max_age = config.cache_duration.as_secs() as i64;
+            cached.retain(|_, cached| answers.fetched_at - cached.fetched_at <= max_age);
+            cached.insert(query, answers);
+            let cache = DuckduckgoCache {
+                queries: cached.clone(),
+            };
+            drop(cached);
+            helpers.write_cache("duckduckgo", &cache);
+            inner.fetching_answers.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Asks the fetching thread for answers unless they are cached
+    fn request_answers(&self, query: &str, cache_duration: Duration) -> Option<QueryAnswers> {
+        let answers = self.inner.answers.lock().unwrap().get(query).cloned();
+        let expired = answers.as_ref().is_none_or(|answers| {
+            chrono::Utc::now().timestamp() - answers.fetched_at > cache_duration.as_secs() as i64
+        });
+        if expired {
+            let mut pending = self.inner.pending.lock().unwrap();
+            if pending.as_ref().is_none_or(|(pending, _)| pending != query) {
+                *pending = Some((query.to_string(), Instant::now()));
+                self.inner.wakeup.notify_one();
+            }
+        }
+        answers
+    }
+
+    /// Fetches the first page of results, or the page of the posted "Next" form
+    fn fetch_results(
+        config: &DuckduckgoConfig,
+        query: &str,
+        next_page: Option<&[(String, String)]>,
+    ) -> Result<Page<SearchResult, NextPageForm>, String> {
+        let response = match next_page {
+            Some(form) => {
+                let form: Vec<(&str, &str)> = form
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str()))
+                    .collect();
+                ureq::post(&config.html_url)
+                    .set("User-Agent", &config.user_agent)
+                    .send_form(&form)
+            }
+            None => get(&config.html_url)
+                .set("User-Agent", &config.user_agent)
+                .query("q", query)
+                .call(),
+        };
+        let response = response
+            .map_err(|err| err.to_string())?
+            .into_string()
+            .map_err(|err| err.to_string())?;
+        Ok(Self::scrape_results(&response))
+    }
+
+    fn scrape_results(response: &str) -> Page<SearchResult, NextPageForm> {
+        let html = Html::parse_document(response);
+        let selector = Selector::parse(".result__body").unwrap();
+        let title_selector = Selector::parse(".result__title > a").unwrap();
+        let mut results = vec![];
+        for element in html.select(&selector) {
+            let Some(title_link) = element.select(&title_selector).next() else {
+                continue;
+            };
+            let title = title_link.text().collect::<String>();
+            let Some(ddg_url) = title_link.attr("href") else {
+                continue;
+            };
+            let encoded_url = ddg_url
+                .split("uddg=")
+                .last()
+                .unwrap()
+                .split("&rut")
+                .next()
+                .unwrap();
+            let Ok(url) = urlencoding::decode(encoded_url) else {
+                continue;
+            };
+            results.push(SearchResult {
+                title,
+                url: url.to_string(),
+            });
+        }
+        // Pages link to each other with forms, the next one has a "Next" button
+        let form_selector = Selector::parse(".nav-link form").unwrap();
+        let submit_selector = Selector::parse("input[type=submit]").unwrap();
+        let hidden_selector = Selector::parse("input[type=hidden]").unwrap();
+        let next_page = html
+            .select(&form_selector)
+            .find(|form| {
+                form.select(&submit_selector)
+                    .any(|submit| submit.attr("value") == Some("Next"))
+            })
+            .map(|form| {
+                form.select(&hidden_selector)
+                    .filter_map(|input| {
+                        Some((
+                            input.attr("name")?.to_string(),
+                            input.attr("value").unwrap_or_default().to_string(),
+                        ))
+                    })
+                    .collect()
+            });
+        Page { results, next_page }
+    }
+
+    /// Fetches results in a separate thread, keeping the launcher open with the query.
+    /// Without `more` the results of the query are replaced, otherwise the next page is appended
+    fn search_action(&self, query: String, more: bool) -> Rc<dyn Fn(&mut crate::os::Os) -> bool> {
+        let searches = self.inner.searches.clone();
+        let config = self.config.clone().unwrap();
+        Rc::new(move |os| {
+            let config = config.clone();
+            let search_query = query.clone();
+            searches.fetch(&query, more, move |next_page| {
+                Self::fetch_results(&config, &search_query, next_page.as_deref())
+            });
+            os.set_query(query.clone());
+            false
+        })
+    }
+
+    fn build_result_items(
+        &self,
+        query: &str,
+        searched: PagedResults<SearchResult, NextPageForm>,
+    ) -> Vec<SearchItem> {
+        let count = searched.results.len();
+        let mut items: Vec<SearchItem> = searched
+            .results
+            .into_iter()
+            .enumerate()
+            .map(|(index, result)| {
+                let url = result.url.clone();
+                SearchItem {
+                    // Keyed by URL, so history boosts the link instead of every result
+                    id: format!("{}:{}", self.name(), result.url),
+                    title: Some(result.title),
+                    subtitle: Some(result.url),
+                    icon: None,
+                    image: None,
+                    // Keeps the ranking of DuckDuckGo
+                    score: (count - index) as i64,
+                    source: self.name(),
+                    action: Rc::new(move |os| {
+                        let result = os.open_url(&url);
+                        os.report(result)
+                    }),
+                    layer: ItemLayer::Top,
+                }
+            })
+            .collect();
+        if searched.fetching {
+            items.push(self.build_search_item(query, "Searching DuckDuckGo", false));
+            return items;
+        }
+        if let Some(error) = searched.error {
+            items.push(SearchItem::error(format!(
+                "Failed to search DuckDuckGo: {}",
+                error
+            )));
+        }
+        if searched.next_page.is_some() {
+            items.push(self.build_search_item(query, "More results", true));
+        } else if count == 0 {
+            items.push(self.build_search_item(query, "Search DuckDuckGo", false));
+        }
+        items
+    }
+
+    /// Item searching the query, or with `more` fetching its next page
+    fn build_search_item(&self, query: &str, title: &str, more: bool) -> SearchItem {
+        SearchItem {
+            id: if more {
+                format!("{}:more", self.name())
+            } else {
+                self.name().to_string()
+            },
+            title: Some(title.to_string()),
+            subtitle: Some(format!("{} (DuckDuckGo)", query)),
+            icon: None,
+            image: None,
+            score: 0,
+            source: self.name(),
+            action: self.search_action(query.to_string(), more),
+            layer: ItemLayer::Top,
+        }
+    }
+
+    fn build_answer_items(&self, answers: &QueryAnswers) -> Vec<SearchItem> {
+        let mut items = vec![];
+        for (index, answer) in answers.instant_answers.iter().enumerate() {
+            let url = answer.url.clone();
+            let text = answer.title.clone();
+            items.push(SearchItem {
+                id: format!("{}:answer:{}", self.name(), answer.title),
+                title: Some(answer.title.clone()),
+                subtitle: Some(answer.subtitle.clone()),
+                icon: None,
+                image: None,
+                score: (answers.instant_answers.len() - index) as i64 + 1000,
+                source: self.name(),
+                action: Rc::new(move |os| {
+                    if url.is_empty() {
+                        let result = os.copy_to_clipboard(ClipboardContent::text(text.clone()));
+                        return os.report(result);
+                    }
+                    let result = os.open_url(&url);
+                    os.report(result)
+                }),
+                layer: ItemLayer::Top,
+            });
+        }
+        for (index, suggestion) in answers.suggestions.iter().enumerate() {
+            items.push(SearchItem {
+                id: format!("{}:suggestion:{}", self.name(), suggestion),
+                title: Some(suggestion.clone()),
+                subtitle: Some("Search DuckDuckGo".to_string()),
+                icon: None,
+                image: None,
+                score: (answers.suggestions.len() - index) as i64,
+                source: self.name(),
+                action: self.search_action(suggestion.clone(), false),
+                layer: ItemLayer::Middle,
+            });
+        }
+        items
+    }
 }
 
 impl Source for DuckduckgoSource {
@@ -68,95 +491,194 @@ impl Source for DuckduckgoSource {
 
     fn init(&mut self, config: &toml::Table, helpers: Arc<Helpers>) {
         let config: DuckduckgoConfig = config.clone().try_into().unwrap();
-        let cache_duration = config.cache_duration;
-
-        if !helpers.cache_expired(self.name(), cache_duration) {}
+        if let Some(cache) = helpers.read_cache::<DuckduckgoCache>(self.name()) {
+            *self.inner.answers.lock().unwrap() = cache.queries;
+        }
+        if config.suggestions || config.instant_answers {
+            let inner = self.inner.clone();
+            let fetcher_config = config.clone();
+            std::thread::spawn(move || Self::run_fetcher(inner, fetcher_config, helpers));
+        }
         self.config = Some(config);
     }
 
+    fn loading(&self) -> bool {
+        self.inner.fetching_answers.load(Ordering::SeqCst)
+            || self.inner.pending.lock().unwrap().is_some()
+            || self.inner.searches.loading()
+    }
+
     fn deinit(&mut self) {}
 
     fn search(
         &self,
         query: &str,
-        matcher: &Box<dyn fuzzy_matcher::FuzzyMatcher + Send + Sync>,
+        _matcher: &Box<dyn fuzzy_matcher::FuzzyMatcher + Send + Sync>,
     ) -> Vec<crate::model::SearchItem> {
-        let state = self.state.clone();
-        let current_state = { state.read().unwrap().clone() };
-        match &current_state {
-            DuckduckgoSearchState::None => {
-                let user_agent = self.config.as_ref().unwrap().user_agent.clone();
-                let query = query.to_string();
-                let item = crate::model::SearchItem {
-                    id: self.name().to_string(),
-                    title: Some("Search DuckDuckGo".to_string()),
-                    subtitle: Some(query.to_string()),
-                    icon: None,
-                    image: None,
-                    score: 0,
-                    source: self.name(),
-                    action: Box::new(move |os| {
-                        let user_agent = user_agent.clone();
-                        let state = state.clone();
-                        let url = format!("https://html.duckduckgo.com/html/?q={}", query);
-                        let response = get(&url)
-                            .set("User-Agent", &user_agent)
-                            .call()
-                            .unwrap()
-                            .into_string()
-                            .unwrap();
-                        let html = Html::parse_document(&response);
-                        let selector = Selector::parse(".result__body").unwrap();
-                        let mut results = vec![];
-                        for element in html.select(&selector) {
-                            let title_link = element
-                                .select(&Selector::parse(".result__title > a").unwrap())
-                                .next()
-                                .unwrap();
-                            let title = title_link.text().next().unwrap().to_string();
-                            let ddg_url = title_link.attr("href").unwrap().to_string();
-                            let encoded_url = ddg_url
-                                .split("uddg=")
-                                .last()
-                                .unwrap()
-                                .split("&rut")
-                                .next()
-                                .unwrap()
-                                .to_string();
-                            let url = urlencoding::decode(&encoded_url)
-                                .expect("UTF-8")
-                                .to_string();
-                            results.push(SearchResult { title, url });
-                        }
-                        *state.write().unwrap() = DuckduckgoSearchState::Searched(results);
-                        false
-                    }),
-                    layer: crate::model::ItemLayer::Top,
-                };
-                vec![item]
+        let config = self.config.as_ref().unwrap();
+        let query = query.trim();
+        if let Some(searched) = self.inner.searches.get(query) {
+            return self.build_result_items(query, searched);
+        }
+        let mut items = vec![self.build_search_item(query, "Search DuckDuckGo", false)];
+        if !query.is_empty() && (config.suggestions || config.instant_answers) {
+            if let Some(answers) = self.request_answers(query, config.cache_duration) {
+                items.extend(self.build_answer_items(&answers));
             }
-            DuckduckgoSearchState::Searched(items) => items
-                .iter()
-                .map(|item| {
-                    let title = item.title.clone();
-                    let url = item.url.clone();
-                    let score = matcher.fuzzy_match(&title, &query).unwrap_or(0);
-                    crate::model::SearchItem {
-                        id: self.name().to_string(),
-                        title: Some(title),
-                        subtitle: Some(url.clone()),
-                        icon: None,
-                        image: None,
-                        score,
-                        source: self.name(),
-                        action: Box::new(move |os| {
-                            os.open_url(&url);
-                            true
-                        }),
-                        layer: crate::model::ItemLayer::Top,
-                    }
-                })
-                .collect(),
         }
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+    use fuzzy_matcher::FuzzyMatcher;
+    use std::sync::mpsc;
+
+    fn source(server: &TestServer, cache_dir: &tempfile::TempDir) -> DuckduckgoSource {
+        let config: toml::Table = toml::from_str(&format!(
+            r#"
+            debounce = 0
+            html_url = "{url}/html/"
+            autocomplete_url = "{url}/ac/"
+            instant_answer_url = "{url}/ia/"
+            "#,
+            url = server.url
+        ))
+        .unwrap();
+        let helpers = Helpers::with_cache_dir(cache_dir.path().to_path_buf());
+        let mut source = DuckduckgoSource::new();
+        source.init(&config, Arc::new(helpers));
+        source
+    }
+
+    fn search(source: &DuckduckgoSource, query: &str) -> Vec<String> {
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        let mut items = source.search(query, &matcher);
+        items.sort_by_key(|item| std::cmp::Reverse(item.score));
+        items
+            .into_iter()
+            .map(|item| item.title.unwrap_or_default())
+            .collect()
+    }
+
+    /// What a UI does while `loading` is true
+    fn wait_until_loaded(source: &DuckduckgoSource) {
+        let started = Instant::now();
+        while source.loading() {
+            assert!(started.elapsed() < Duration::from_secs(5), "still loading");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn suggestions_and_instant_answers_arrive_after_typing() {
+        let server = TestServer::start(vec![
+            (
+                "/ac/",
+                "application/json",
+                r#"["rust", ["rust lang", "rust book"]]"#.to_string(),
+            ),
+            (
+                "/ia/",
+                "application/json",
+                r#"{"Heading": "Rust", "AbstractText": "A programming language",
+                    "AbstractURL": "https://en.wikipedia.org/wiki/Rust",
+                    "AbstractSource": "Wikipedia", "Answer": ""}"#
+                    .to_string(),
+            ),
+        ]);
+        let cache_dir = tempfile::tempdir().unwrap();
+        let source = source(&server, &cache_dir);
+
+        assert_eq!(search(&source, "rust"), vec!["Search DuckDuckGo"]);
+        // The UI keeps refreshing because typing started a fetch
+        assert!(source.loading());
+        wait_until_loaded(&source);
+        assert_eq!(
+            search(&source, "rust"),
+            vec!["Rust", "rust lang", "rust book", "Search DuckDuckGo"]
+        );
+        let requests = server.requests();
+        assert!(requests.contains(&"GET /ac/?q=rust&type=list".to_string()));
+        assert!(requests
+            .iter()
+            .any(|request| request.starts_with("GET /ia/?q=rust")));
+    }
+
+    const RESULTS_PAGE: &str = r#"<html><body>
+        <div class="result__body">
+            <h2 class="result__title"><a href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fwww.rust-lang.org%2F&rut=1">Rust</a></h2>
+        </div>
+        <div class="result__body">
+            <h2 class="result__title"><a href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fdoc.rust-lang.org%2Fbook%2F&rut=2">The Book</a></h2>
+        </div>
+        <div class="nav-link">
+            <form action="/html/" method="post">
+                <input type="submit" value="Next">
+                <input type="hidden" name="q" value="rust">
+                <input type="hidden" name="s" value="10">
+            </form>
+        </div>
+        </body></html>"#;
+
+    #[test]
+    fn results_are_fetched_page_by_page() {
+        let server = TestServer::start(vec![("/html/", "text/html", RESULTS_PAGE.to_string())]);
+        let cache_dir = tempfile::tempdir().unwrap();
+        let source = source(&server, &cache_dir);
+        let config = source.config.clone().unwrap();
+        let fetch = move |next_page: Option<NextPageForm>| {
+            DuckduckgoSource::fetch_results(&config, "rust", next_page.as_deref())
+        };
+
+        // Held back until the pending search was shown
+        let (release, released) = mpsc::channel::<()>();
+        let first_fetch = fetch.clone();
+        source
+            .inner
+            .searches
+            .fetch("rust", false, move |next_page| {
+                released.recv().unwrap();
+                first_fetch(next_page)
+            });
+        assert_eq!(search(&source, "rust"), vec!["Searching DuckDuckGo"]);
+        release.send(()).unwrap();
+        wait_until_loaded(&source);
+        assert_eq!(
+            search(&source, "rust"),
+            vec!["Rust", "The Book", "More results"]
+        );
+
+        source.inner.searches.fetch("rust", true, fetch);
+        wait_until_loaded(&source);
+        assert_eq!(
+            search(&source, "rust"),
+            vec!["Rust", "The Book", "Rust", "The Book", "More results"]
+        );
+        assert_eq!(
+            server.requests(),
+            vec!["GET /html/?q=rust", "POST /html/ q=rust&s=10"]
+        );
+    }
+
+    #[test]
+    fn answers_are_cached_across_launches() {
+        let server = TestServer::start(vec![(
+            "/ac/",
+            "application/json",
+            r#"["rust", ["rust lang"]]"#.to_string(),
+        )]);
+        let cache_dir = tempfile::tempdir().unwrap();
+        let source_before = source(&server, &cache_dir);
+        search(&source_before, "rust");
+        wait_until_loaded(&source_before);
+
+        let source_after = source(&server, &cache_dir);
+        assert!(search(&source_after, "rust").contains(&"rust lang".to_string()));
+        assert!(!source_after.loading());
     }
 }
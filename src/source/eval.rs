@@ -1,3 +1,4 @@
+use std::rc::Rc;
 use std::sync::Arc;
 
 use crate::helpers::Helpers;
@@ -5,12 +6,22 @@ use crate::model::{ClipboardContent, SearchItem};
 use crate::source::Source;
 use eval::Expr;
 use fuzzy_matcher::FuzzyMatcher;
+use serde::Deserialize;
 
-pub struct EvalSource {}
+#[derive(Deserialize)]
+pub struct EvalConfig {
+    /// Paste the result into the focused window after copying
+    #[serde(default)]
+    pub paste: bool,
+}
+
+pub struct EvalSource {
+    paste: bool,
+}
 
 impl EvalSource {
     pub fn new() -> Self {
-        Self {}
+        Self { paste: false }
     }
 }
 
@@ -19,7 +30,10 @@ impl Source for EvalSource {
         "eval"
     }
 
-    fn init(&mut self, _config: &toml::Table, _helpers: Arc<Helpers>) {}
+    fn init(&mut self, config: &toml::Table, _helpers: Arc<Helpers>) {
+        let config: EvalConfig = config.clone().try_into().unwrap();
+        self.paste = config.paste;
+    }
 
     fn deinit(&mut self) {}
 
@@ -36,6 +50,7 @@ impl Source for EvalSource {
             }
             let value_text = value.to_string();
             let action_value_text = value_text.clone();
+            let paste = self.paste;
             let item = SearchItem {
                 id: self.name().to_string(),
                 title: Some(value_text),
@@ -45,10 +60,8 @@ impl Source for EvalSource {
                 score: 1,
                 source: self.name(),
                 layer: crate::model::ItemLayer::Top,
-                action: Box::new(move |os| {
-                    let text_bytes = action_value_text.clone().into_bytes();
-                    os.copy_to_clipboard(ClipboardContent(text_bytes));
-                    true
+                action: Rc::new(move |os| {
+                    os.copy_and_paste(ClipboardContent::text(action_value_text.clone()), paste)
                 }),
             };
             return vec![item];
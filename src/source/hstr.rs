@@ -4,6 +4,7 @@ use crate::source::Source;
 use fuzzy_matcher::FuzzyMatcher;
 
 use std::process::Command;
+use std::rc::Rc;
 use std::sync::Arc;
 
 pub struct HstrSource {}
@@ -52,11 +53,15 @@ impl Source for HstrSource {
                     image: None,
                     score,
                     source: self.name(),
-                    action: Box::new(move |os| {
+                    action: Rc::new(move |os| {
                         let shell = std::env::var("SHELL").unwrap_or("bash".to_string());
-                        let command = format!("{} -c '{};exec $SHELL;'", shell, action_command);
-                        os.run_in_terminal(&command);
-                        true
+                        let args = vec![
+                            shell,
+                            "-c".to_string(),
+                            format!("{};exec \"$SHELL\";", action_command),
+                        ];
+                        let result = os.run_app_in_terminal(&args, None, None);
+                        os.report(result)
                     }),
                     layer: crate::model::ItemLayer::Middle,
                 }
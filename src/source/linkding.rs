@@ -1,25 +1,37 @@
 use crate::helpers::Helpers;
 use crate::model::SearchItem;
 use crate::source::Source;
+use chrono::{DateTime, Utc};
 use fuzzy_matcher::FuzzyMatcher;
+use log::warn;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::process::Command;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
-use std::time::Duration;
-use ureq::get;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use ureq::{get, post};
+
+/// Starts a query which saves a bookmark, e.g. `+ https://example.com #rust #docs Example`
+const CREATE_PREFIX: &str = "+";
 
 fn _default_limit() -> u32 {
     100
 }
 
+const fn _default_debounce() -> u64 {
+    300
+}
+
 fn _default_cache_duration() -> Duration {
     // 24 hours
     Duration::from_secs(60 * 60 * 24)
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 struct Bookmark {
     id: u32,
     url: String,
@@ -43,6 +55,87 @@ struct Bookmarks {
     next: Option<String>,
     previous: Option<String>,
     results: Vec<Bookmark>,
+    /// Start of the last complete sync, later syncs only fetch bookmarks modified since
+    #[serde(default)]
+    synced_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Tag {
+    id: u32,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tags {
+    next: Option<String>,
+    results: Vec<Tag>,
+}
+
+/// Body of `POST /api/bookmarks/`, which also updates the bookmark if the URL is already saved
+#[derive(Serialize)]
+struct NewBookmark {
+    url: String,
+    title: String,
+    tag_names: Vec<String>,
+}
+
+#[derive(Deserialize, Clone)]
+struct WebsiteMetadata {
+    title: Option<String>,
+}
+
+/// Answer of `/api/bookmarks/check/`
+#[derive(Deserialize, Clone)]
+struct BookmarkCheck {
+    bookmark: Option<Bookmark>,
+    metadata: WebsiteMetadata,
+}
+
+#[derive(Clone)]
+enum CheckState {
+    Pending,
+    Checked(Box<BookmarkCheck>),
+    Failed,
+}
+
+/// Talks to the linkding REST API
+#[derive(Clone)]
+struct LinkdingClient {
+    host: String,
+    api_key: String,
+}
+
+impl LinkdingClient {
+    fn authorization(&self) -> String {
+        format!("Token {}", self.api_key)
+    }
+
+    fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, String> {
+        get(url)
+            .set("Authorization", &self.authorization())
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())
+    }
+
+    fn check(&self, url: &str) -> Result<BookmarkCheck, String> {
+        self.get(&format!(
+            "{}/api/bookmarks/check/?url={}",
+            self.host,
+            urlencoding::encode(url)
+        ))
+    }
+
+    fn create(&self, bookmark: &NewBookmark) -> Result<Bookmark, String> {
+        post(&format!("{}/api/bookmarks/", self.host))
+            .set("Authorization", &self.authorization())
+            .send_json(bookmark)
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())
+    }
 }
 
 #[derive(Deserialize)]
@@ -51,16 +144,33 @@ pub struct LinkdingConfig {
     pub host: String,
     #[serde(default = "_default_limit")]
     pub limit: u32,
+    /// Cached bookmarks older than this are fetched completely instead of as a delta
     #[serde(default = "_default_cache_duration")]
     pub cache_duration: Duration,
+    /// Also sync archived bookmarks
+    #[serde(default)]
+    pub archived: bool,
+    /// Milliseconds without typing before a URL to save is checked against the server
+    #[serde(default = "_default_debounce")]
+    pub debounce: u64,
 }
 
 struct LinkdingSourceInner {
     bookmarks: Mutex<Vec<Bookmark>>,
+    tags: Mutex<Vec<Tag>>,
+    synced_at: Mutex<Option<String>>,
+    syncing: AtomicBool,
+    /// Latest URL typed after the create prefix and when it was typed, picked up by the checking thread
+    pending_check: Mutex<Option<(String, Instant)>>,
+    check_wakeup: Condvar,
+    /// Check of the latest URL, earlier ones are dropped
+    check: Mutex<Option<(String, CheckState)>>,
 }
 
 pub struct LinkdingSource {
     inner: Arc<LinkdingSourceInner>,
+    client: Option<LinkdingClient>,
+    helpers: Option<Arc<Helpers>>,
 }
 
 impl LinkdingSource {
@@ -68,8 +178,377 @@ impl LinkdingSource {
         LinkdingSource {
             inner: Arc::new(LinkdingSourceInner {
                 bookmarks: Mutex::new(vec![]),
+                tags: Mutex::new(vec![]),
+                synced_at: Mutex::new(None),
+                syncing: AtomicBool::new(false),
+                pending_check: Mutex::new(None),
+                check_wakeup: Condvar::new(),
+                check: Mutex::new(None),
             }),
+            client: None,
+            helpers: None,
+        }
+    }
+
+    /// Replaces bookmarks with the same id and appends new ones
+    fn merge(bookmarks: &mut Vec<Bookmark>, page: Vec<Bookmark>) {
+        let mut positions: HashMap<u32, usize> = bookmarks
+            .iter()
+            .enumerate()
+            .map(|(position, bookmark)| (bookmark.id, position))
+            .collect();
+        for bookmark in page {
+            match positions.get(&bookmark.id) {
+                Some(&position) => bookmarks[position] = bookmark,
+                None => {
+                    positions.insert(bookmark.id, bookmarks.len());
+                    bookmarks.push(bookmark);
+                }
+            }
+        }
+    }
+
+    fn write_cache(
+        helpers: &Helpers,
+        name: &str,
+        bookmarks: &[Bookmark],
+        synced_at: Option<String>,
+    ) {
+        let cache = Bookmarks {
+            count: bookmarks.len() as u32,
+            next: None,
+            previous: None,
+            results: bookmarks.to_vec(),
+            synced_at,
+        };
+        helpers.write_cache(name, &cache);
+    }
+
+    fn tags_cache_key(name: &str) -> String {
+        format!("{}_tags", name)
+    }
+
+    /// Whether the last sync is older than the cache duration
+    fn sync_expired(synced_at: Option<&str>, cache_duration: Duration) -> bool {
+        let Some(synced_at) = synced_at.and_then(|at| DateTime::parse_from_rfc3339(at).ok()) else {
+            return true;
+        };
+        (Utc::now() - synced_at.with_timezone(&Utc))
+            .to_std()
+            .is_ok_and(|age| age > cache_duration)
+    }
+
+    /// Follows the `next` links of a listing to the end, handing over one page at a time
+    fn fetch_pages(
+        client: &LinkdingClient,
+        url: String,
+        mut on_page: impl FnMut(Bookmarks),
+    ) -> Result<(), String> {
+        let mut next = Some(url);
+        while let Some(url) = next {
+            let mut page: Bookmarks = client.get(&url)?;
+            next = page.next.take();
+            on_page(page);
+        }
+        Ok(())
+    }
+
+    /// Fetches the bookmarks modified since `synced_at`, or all of them for the first sync or
+    /// when bookmarks were deleted, merging every page as it arrives
+    fn sync_bookmarks(
+        client: &LinkdingClient,
+        config: &LinkdingConfig,
+        inner: &LinkdingSourceInner,
+        synced_at: Option<&str>,
+    ) -> Result<(), String> {
+        let mut endpoints = vec!["/api/bookmarks/"];
+        if config.archived {
+            endpoints.push("/api/bookmarks/archived/");
+        }
+        let merge_page =
+            |page: Bookmarks| Self::merge(&mut inner.bookmarks.lock().unwrap(), page.results);
+
+        let full_sync = match synced_at {
+            Some(since) => {
+                for endpoint in &endpoints {
+                    let url = format!(
+                        "{}{}?limit={}&modified_since={}",
+                        config.host,
+                        endpoint,
+                        config.limit,
+                        urlencoding::encode(since)
+                    );
+                    Self::fetch_pages(client, url, merge_page)?;
+                }
+                // Deleted bookmarks are not part of the delta, only the count reveals them
+                let mut count = 0;
+                for endpoint in &endpoints {
+                    let url = format!("{}{}?limit=1", config.host, endpoint);
+                    count += client.get::<Bookmarks>(&url)?.count as usize;
+                }
+                count != inner.bookmarks.lock().unwrap().len()
+            }
+            None => true,
+        };
+        if full_sync {
+            let mut synced_ids = HashSet::new();
+            for endpoint in &endpoints {
+                let url = format!("{}{}?limit={}", config.host, endpoint, config.limit);
+                Self::fetch_pages(client, url, |page| {
+                    synced_ids.extend(page.results.iter().map(|bookmark| bookmark.id));
+                    merge_page(page);
+                })?;
+            }
+            // Only drop bookmarks once every page arrived
+            inner
+                .bookmarks
+                .lock()
+                .unwrap()
+                .retain(|bookmark| synced_ids.contains(&bookmark.id));
+        }
+        Ok(())
+    }
+
+    /// Syncs bookmarks and tags, bookmarks as a delta unless the cache expired. The bookmarks are
+    /// cached once after their sync, a failed sync still caches the pages fetched before it failed
+    /// and the next one starts from the same point
+    fn sync(
+        client: &LinkdingClient,
+        config: &LinkdingConfig,
+        inner: &LinkdingSourceInner,
+        helpers: &Helpers,
+        name: &str,
+    ) -> Result<(), String> {
+        let started_at = Utc::now().to_rfc3339();
+        let synced_at = inner.synced_at.lock().unwrap().clone();
+        let since = synced_at
+            .as_deref()
+            .filter(|synced_at| !Self::sync_expired(Some(synced_at), config.cache_duration));
+        let synced = Self::sync_bookmarks(client, config, inner, since);
+        if synced.is_ok() {
+            *inner.synced_at.lock().unwrap() = Some(started_at);
+        }
+        let synced_at = inner.synced_at.lock().unwrap().clone();
+        let bookmarks = inner.bookmarks.lock().unwrap().clone();
+        Self::write_cache(helpers, name, &bookmarks, synced_at);
+        synced?;
+
+        let mut tags = vec![];
+        let mut next = Some(format!("{}/api/tags/?limit={}", config.host, config.limit));
+        while let Some(url) = next {
+            let page: Tags = client.get(&url)?;
+            next = page.next;
+            tags.extend(page.results);
+        }
+        let cache = Tags {
+            next: None,
+            results: tags.clone(),
+        };
+        helpers.write_cache(&Self::tags_cache_key(name), &cache);
+        *inner.tags.lock().unwrap() = tags;
+        Ok(())
+    }
+
+    /// Suggests tags completing the `#partial` word at the end of the query.
+    /// An empty `#` lists all tags, most used first
+    fn build_tag_items(
+        &self,
+        query: &str,
+        matcher: &(dyn FuzzyMatcher + Send + Sync),
+    ) -> Vec<SearchItem> {
+        if query.ends_with(char::is_whitespace) {
+            return vec![];
+        }
+        let Some(partial) = query
+            .split_whitespace()
+            .last()
+            .and_then(|word| word.strip_prefix('#'))
+        else {
+            return vec![];
+        };
+        let mut counts: HashMap<String, i64> = HashMap::new();
+        for bookmark in self.inner.bookmarks.lock().unwrap().iter() {
+            for tag in &bookmark.tag_names {
+                *counts.entry(tag.clone()).or_default() += 1;
+            }
+        }
+        // Tags without bookmarks are only known from the API
+        for tag in self.inner.tags.lock().unwrap().iter() {
+            counts.entry(tag.name.clone()).or_default();
+        }
+        let prefix = &query[..query.len() - partial.len() - 1];
+        counts
+            .into_iter()
+            .filter_map(|(tag, count)| {
+                let score = if partial.is_empty() {
+                    count
+                } else {
+                    matcher.fuzzy_match(&tag, partial)?
+                };
+                let completed = format!("{}#{} ", prefix, tag);
+                Some(SearchItem {
+                    id: format!("{}:tag:{}", self.name(), tag),
+                    title: Some(format!("#{}", tag)),
+                    subtitle: Some(format!(
+                        "{} bookmark{}",
+                        count,
+                        if count == 1 { "" } else { "s" }
+                    )),
+                    icon: None,
+                    image: None,
+                    score,
+                    source: self.name(),
+                    layer: crate::model::ItemLayer::Top,
+                    action: Rc::new(move |os| {
+                        os.set_query(completed.clone());
+                        false
+                    }),
+                })
+            })
+            .collect()
+    }
+
+    /// Splits `+ <url> #tag1 #tag2 optional title` into the bookmark to save
+    fn parse_create(query: &str) -> Option<NewBookmark> {
+        let rest = query.strip_prefix(CREATE_PREFIX)?;
+        let mut words = rest.split_whitespace();
+        let url = words.next()?.to_string();
+        let (tags, title): (Vec<&str>, Vec<&str>) = words.partition(|word| word.starts_with('#'));
+        Some(NewBookmark {
+            url,
+            title: title.join(" "),
+            tag_names: tags
+                .into_iter()
+                .map(|tag| tag[1..].to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        })
+    }
+
+    /// Whether `text` is a complete http(s) URL, only those are checked
+    fn is_http_url(text: &str) -> bool {
+        url::Url::parse(text)
+            .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some())
+    }
+
+    /// Returns the check of the URL, asking the checking thread for it if it is not known yet.
+    /// `None` if the text is not an http(s) URL
+    fn check(&self, url: &str) -> Option<CheckState> {
+        if !Self::is_http_url(url) {
+            return None;
+        }
+        if let Some((checked, state)) = &*self.inner.check.lock().unwrap() {
+            if checked == url {
+                return Some(state.clone());
+            }
+        }
+        let mut pending = self.inner.pending_check.lock().unwrap();
+        if pending.as_ref().is_none_or(|(pending, _)| pending != url) {
+            *pending = Some((url.to_string(), Instant::now()));
+            self.inner.check_wakeup.notify_one();
         }
+        Some(CheckState::Pending)
+    }
+
+    /// Checks the latest typed URL once typing paused, so partial URLs are not sent.
+    /// Runs for the lifetime of the launcher
+    fn run_checker(inner: Arc<LinkdingSourceInner>, client: LinkdingClient, debounce: Duration) {
+        loop {
+            let mut pending = inner.pending_check.lock().unwrap();
+            while pending.is_none() {
+                pending = inner.check_wakeup.wait(pending).unwrap();
+            }
+            let (url, typed_at) = pending.clone().unwrap();
+            let wait = debounce.saturating_sub(typed_at.elapsed());
+            if !wait.is_zero() {
+                // The URL may change while waiting, check again afterwards
+                drop(pending);
+                std::thread::sleep(wait);
+                continue;
+            }
+            *pending = None;
+            *inner.check.lock().unwrap() = Some((url.clone(), CheckState::Pending));
+            drop(pending);
+
+            let state = match client.check(&url) {
+                Ok(check) => CheckState::Checked(Box::new(check)),
+                Err(err) => {
+                    warn!("Failed to check bookmark {}: {}", url, err);
+                    CheckState::Failed
+                }
+            };
+            *inner.check.lock().unwrap() = Some((url, state));
+        }
+    }
+
+    fn build_create_item(&self, mut bookmark: NewBookmark) -> Option<SearchItem> {
+        let client = self.client.clone()?;
+        let helpers = self.helpers.clone()?;
+        let state = self.check(&bookmark.url);
+        let existing = match &state {
+            Some(CheckState::Checked(check)) => check.bookmark.clone(),
+            _ => None,
+        };
+        // Posting replaces the tags of a saved bookmark, keep them
+        if let Some(existing) = &existing {
+            for tag in &existing.tag_names {
+                if !bookmark.tag_names.contains(tag) {
+                    bookmark.tag_names.push(tag.clone());
+                }
+            }
+        }
+        if bookmark.title.is_empty() {
+            bookmark.title = match (&state, &existing) {
+                (_, Some(existing)) => existing.title.clone(),
+                (Some(CheckState::Checked(check)), None) => {
+                    check.metadata.title.clone().unwrap_or_default()
+                }
+                _ => String::new(),
+            };
+        }
+        let status = match (&state, &existing) {
+            (None, _) => "not an http(s) URL",
+            (Some(CheckState::Pending), _) => "checking",
+            (_, Some(_)) => "already saved, updates it",
+            (Some(CheckState::Checked(_)), None) => "new",
+            (Some(CheckState::Failed), None) => "could not check",
+        };
+        let tags = bookmark
+            .tag_names
+            .iter()
+            .map(|tag| "#".to_string() + tag)
+            .collect::<Vec<String>>()
+            .join(", ");
+        let title = if bookmark.title.is_empty() {
+            format!("Save bookmark {}", bookmark.url)
+        } else {
+            format!("Save bookmark {}", bookmark.title)
+        };
+        let inner = self.inner.clone();
+        let name = self.name();
+        Some(SearchItem {
+            id: format!("{}:create", self.name()),
+            title: Some(title),
+            subtitle: Some(format!("{} {} ({})", bookmark.url, tags, status)),
+            icon: None,
+            image: None,
+            score: 1,
+            source: self.name(),
+            layer: crate::model::ItemLayer::Top,
+            action: Rc::new(move |os| {
+                let saved = match client.create(&bookmark) {
+                    Ok(saved) => saved,
+                    Err(err) => return os.report(Err(format!("Failed to save bookmark: {}", err))),
+                };
+                // Update the cache right away instead of waiting for the next sync
+                let mut bookmarks = inner.bookmarks.lock().unwrap();
+                Self::merge(&mut bookmarks, vec![saved]);
+                let synced_at = inner.synced_at.lock().unwrap().clone();
+                Self::write_cache(&helpers, name, &bookmarks, synced_at);
+                *inner.check.lock().unwrap() = None;
+                true
+            }),
+        })
     }
 }
 impl Source for LinkdingSource {
@@ -79,34 +558,47 @@ impl Source for LinkdingSource {
 
     fn init(&mut self, config: &toml::Table, helpers: Arc<Helpers>) {
         let config: LinkdingConfig = config.clone().try_into().unwrap();
-        let limit = config.limit;
-        let cache_duration = config.cache_duration;
-        if !helpers.cache_expired(self.name(), cache_duration) {
-            let bookmarks: Bookmarks = helpers.read_cache(self.name()).unwrap();
+        let client = LinkdingClient {
+            host: config.host.clone(),
+            api_key: config.api_key.clone(),
+        };
+        self.client = Some(client.clone());
+        self.helpers = Some(helpers.clone());
+        // Cached bookmarks are shown right away and revalidated in the background on every launch
+        if let Some(bookmarks) = helpers.read_cache::<Bookmarks>(self.name()) {
             *self.inner.bookmarks.lock().unwrap() = bookmarks.results;
-            return;
+            *self.inner.synced_at.lock().unwrap() = bookmarks.synced_at;
         }
-        // run in a separate thread
-        let host = config.host.clone();
-        let api_key = config.api_key.clone();
-        let limit = config.limit.clone();
-        let helpers = helpers.clone();
+        if let Some(tags) = helpers.read_cache::<Tags>(&Self::tags_cache_key(self.name())) {
+            *self.inner.tags.lock().unwrap() = tags.results;
+        }
+        let inner = self.inner.clone();
+        let checker_client = client.clone();
+        let debounce = Duration::from_millis(config.debounce);
+        std::thread::spawn(move || Self::run_checker(inner, checker_client, debounce));
+
         let inner = self.inner.clone();
         let name = self.name();
-        let helpers = helpers.clone();
+        inner.syncing.store(true, Ordering::SeqCst);
         std::thread::spawn(move || {
-            let bookmarks_url = format!("{}/api/bookmarks/?limit={}", host, limit);
-            let bookmarks: Bookmarks = get(&bookmarks_url)
-                .set("Authorization", &format!("Token {}", api_key))
-                .call()
-                .expect("Failed to fetch bookmarks")
-                .into_json()
-                .expect("Failed to parse bookmarks");
-            helpers.write_cache(name, &bookmarks);
-            *inner.bookmarks.lock().unwrap() = bookmarks.results;
+            if let Err(err) = Self::sync(&client, &config, &inner, &helpers, name) {
+                warn!("Failed to sync linkding bookmarks: {}", err);
+            }
+            inner.syncing.store(false, Ordering::SeqCst);
         });
     }
 
+    fn loading(&self) -> bool {
+        if self.inner.syncing.load(Ordering::SeqCst) {
+            return true;
+        }
+        self.inner.pending_check.lock().unwrap().is_some()
+            || matches!(
+                &*self.inner.check.lock().unwrap(),
+                Some((_, CheckState::Pending))
+            )
+    }
+
     fn deinit(&mut self) {}
 
     fn search(
@@ -114,6 +606,9 @@ impl Source for LinkdingSource {
         query: &str,
         matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
     ) -> Vec<SearchItem> {
+        if let Some(bookmark) = Self::parse_create(query) {
+            return self.build_create_item(bookmark).into_iter().collect();
+        }
         let query_words: Vec<&str> = query.split_whitespace().collect();
         let query_tags: Vec<&str> = query_words
             .iter()
@@ -133,7 +628,7 @@ impl Source for LinkdingSource {
                 .filter(|bookmark| bookmark.tag_names.contains(&tag.to_string()))
                 .collect();
         }
-        let mut results: Vec<SearchItem> = vec![];
+        let mut results: Vec<SearchItem> = self.build_tag_items(query, matcher.as_ref());
         for bookmark in bookmarks {
             let title_score = matcher
                 .fuzzy_match(&bookmark.title, &query_without_tags)
@@ -173,9 +668,9 @@ impl Source for LinkdingSource {
                 score,
                 source: self.name(),
                 layer: crate::model::ItemLayer::Middle,
-                action: Box::new(move |os| {
-                    os.open_url(&url);
-                    true
+                action: Rc::new(move |os| {
+                    let result = os.open_url(&url);
+                    os.report(result)
                 }),
             });
         }
@@ -183,3 +678,338 @@ impl Source for LinkdingSource {
         results
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+    use std::time::Instant;
+
+    fn bookmark(id: u32, title: &str) -> Bookmark {
+        Bookmark {
+            id,
+            url: format!("https://example.com/{}", id),
+            title: title.to_string(),
+            description: String::new(),
+            notes: String::new(),
+            website_title: None,
+            website_description: None,
+            web_archive_snapshot_url: None,
+            is_archived: false,
+            unread: false,
+            shared: false,
+            tag_names: vec![],
+            date_added: String::new(),
+            date_modified: String::new(),
+        }
+    }
+
+    #[test]
+    fn merge_replaces_known_bookmarks_and_appends_new_ones() {
+        let mut bookmarks = vec![bookmark(1, "one"), bookmark(2, "two")];
+        let page = vec![
+            bookmark(3, "three"),
+            bookmark(1, "one edited"),
+            bookmark(3, "three edited"),
+        ];
+        LinkdingSource::merge(&mut bookmarks, page);
+        let titles: Vec<_> = bookmarks
+            .iter()
+            .map(|bookmark| (bookmark.id, bookmark.title.as_str()))
+            .collect();
+        assert_eq!(
+            titles,
+            vec![(1, "one edited"), (2, "two"), (3, "three edited")]
+        );
+    }
+
+    #[test]
+    fn counts_bookmarks_per_tag() {
+        let source = LinkdingSource::new();
+        let tagged = |id, tags: &[&str]| Bookmark {
+            tag_names: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..bookmark(id, "tagged")
+        };
+        *source.inner.bookmarks.lock().unwrap() =
+            vec![tagged(1, &["rust", "web"]), tagged(2, &["rust"])];
+        *source.inner.tags.lock().unwrap() = vec![Tag {
+            id: 1,
+            name: "unused".to_string(),
+        }];
+        let matcher = SkimMatcherV2::default();
+        let mut tags: Vec<(String, String)> = source
+            .build_tag_items("open #", &matcher)
+            .into_iter()
+            .map(|item| (item.title.unwrap(), item.subtitle.unwrap()))
+            .collect();
+        tags.sort();
+        assert_eq!(
+            tags,
+            [
+                ("#rust".to_string(), "2 bookmarks".to_string()),
+                ("#unused".to_string(), "0 bookmarks".to_string()),
+                ("#web".to_string(), "1 bookmark".to_string()),
+            ]
+        );
+    }
+
+    fn page(results: Vec<Bookmark>, count: u32, next: Option<&str>) -> String {
+        serde_json::to_string(&Bookmarks {
+            count,
+            next: next.map(str::to_string),
+            previous: None,
+            results,
+            synced_at: None,
+        })
+        .unwrap()
+    }
+
+    /// Three bookmarks on two pages of two and one archived, `delta` answers `modified_since`
+    fn server(delta: Vec<Bookmark>) -> TestServer {
+        let json = "application/json";
+        let delta_count = delta.len() as u32;
+        TestServer::start(vec![
+            (
+                "/api/bookmarks/?limit=2&offset=2",
+                json,
+                page(vec![bookmark(3, "three")], 3, None),
+            ),
+            (
+                "/api/bookmarks/?limit=2&modified_since=",
+                json,
+                page(delta, delta_count, None),
+            ),
+            (
+                "/api/bookmarks/?limit=2",
+                json,
+                page(
+                    vec![bookmark(1, "one"), bookmark(2, "two edited")],
+                    3,
+                    Some("{url}/api/bookmarks/?limit=2&offset=2"),
+                ),
+            ),
+            (
+                "/api/bookmarks/?limit=1",
+                json,
+                page(vec![bookmark(1, "one")], 3, None),
+            ),
+            (
+                "/api/bookmarks/archived/?limit=2&modified_since=",
+                json,
+                page(vec![], 0, None),
+            ),
+            (
+                "/api/bookmarks/archived/",
+                json,
+                page(vec![bookmark(4, "archived")], 1, None),
+            ),
+            (
+                "/api/tags/",
+                json,
+                r#"{"next": null, "results": [{"id": 1, "name": "rust"}]}"#.to_string(),
+            ),
+        ])
+    }
+
+    /// Initializes a source against `server`, with `cached` bookmarks synced at `synced_at`,
+    /// and waits for its sync
+    fn sync(
+        server: &TestServer,
+        cache_dir: &tempfile::TempDir,
+        cached: Option<(Vec<Bookmark>, DateTime<Utc>)>,
+    ) -> (LinkdingSource, Arc<Helpers>) {
+        let helpers = Arc::new(Helpers::with_cache_dir(cache_dir.path().to_path_buf()));
+        if let Some((bookmarks, synced_at)) = cached {
+            LinkdingSource::write_cache(
+                &helpers,
+                "linkding",
+                &bookmarks,
+                Some(synced_at.to_rfc3339()),
+            );
+        }
+        let config: toml::Table = toml::from_str(&format!(
+            r#"
+            host = "{}"
+            api_key = "key"
+            limit = 2
+            archived = true
+            "#,
+            server.url
+        ))
+        .unwrap();
+        let mut source = LinkdingSource::new();
+        source.init(&config, helpers.clone());
+        let started = Instant::now();
+        while source.loading() {
+            assert!(started.elapsed() < Duration::from_secs(5), "still syncing");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        (source, helpers)
+    }
+
+    fn titles(bookmarks: &[Bookmark]) -> Vec<(u32, String)> {
+        let mut titles: Vec<_> = bookmarks
+            .iter()
+            .map(|bookmark| (bookmark.id, bookmark.title.clone()))
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    fn synced_titles() -> Vec<(u32, String)> {
+        titles(&[
+            bookmark(1, "one"),
+            bookmark(2, "two edited"),
+            bookmark(3, "three"),
+            bookmark(4, "archived"),
+        ])
+    }
+
+    fn cached() -> Vec<Bookmark> {
+        vec![
+            bookmark(1, "one"),
+            bookmark(2, "two"),
+            bookmark(3, "three"),
+            bookmark(4, "archived"),
+        ]
+    }
+
+    #[test]
+    fn first_sync_follows_all_pages_and_archived_bookmarks() {
+        let server = server(vec![]);
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (source, helpers) = sync(&server, &cache_dir, None);
+
+        assert_eq!(
+            titles(&source.inner.bookmarks.lock().unwrap()),
+            synced_titles()
+        );
+        let requests = server.requests();
+        assert!(requests.contains(&"GET /api/bookmarks/?limit=2&offset=2".to_string()));
+        assert!(requests.contains(&"GET /api/bookmarks/archived/?limit=2".to_string()));
+        assert!(!requests
+            .iter()
+            .any(|request| request.contains("modified_since")));
+
+        let cache: Bookmarks = helpers.read_cache("linkding").unwrap();
+        assert_eq!(titles(&cache.results), synced_titles());
+        assert!(cache.synced_at.is_some());
+        assert_eq!(source.inner.tags.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn cached_bookmarks_are_revalidated_with_a_delta() {
+        let server = server(vec![bookmark(2, "two edited")]);
+        let cache_dir = tempfile::tempdir().unwrap();
+        let (source, _) = sync(&server, &cache_dir, Some((cached(), Utc::now())));
+
+        assert_eq!(
+            titles(&source.inner.bookmarks.lock().unwrap()),
+            synced_titles()
+        );
+        let requests = server.requests();
+        assert!(requests
+            .iter()
+            .any(|request| request.starts_with("GET /api/bookmarks/?limit=2&modified_since=")));
+        // The counts match, no full listing needed
+        assert!(!requests.contains(&"GET /api/bookmarks/?limit=2".to_string()));
+    }
+
+    #[test]
+    fn deleted_bookmarks_fall_back_to_a_full_sync() {
+        let server = server(vec![]);
+        let cache_dir = tempfile::tempdir().unwrap();
+        let mut bookmarks = cached();
+        bookmarks.push(bookmark(5, "deleted"));
+        let (source, _) = sync(&server, &cache_dir, Some((bookmarks, Utc::now())));
+
+        assert_eq!(
+            titles(&source.inner.bookmarks.lock().unwrap()),
+            synced_titles()
+        );
+        let requests = server.requests();
+        assert!(requests
+            .iter()
+            .any(|request| request.contains("modified_since")));
+        assert!(requests.contains(&"GET /api/bookmarks/?limit=2".to_string()));
+    }
+
+    #[test]
+    fn expired_caches_are_synced_completely() {
+        let server = server(vec![]);
+        let cache_dir = tempfile::tempdir().unwrap();
+        let synced_at = Utc::now() - chrono::TimeDelta::days(2);
+        let (source, _) = sync(&server, &cache_dir, Some((cached(), synced_at)));
+
+        assert_eq!(
+            titles(&source.inner.bookmarks.lock().unwrap()),
+            synced_titles()
+        );
+        assert!(!server
+            .requests()
+            .iter()
+            .any(|request| request.contains("modified_since")));
+    }
+
+    #[test]
+    fn only_the_url_typed_last_is_checked() {
+        let server = TestServer::start(vec![(
+            "/api/bookmarks/check/",
+            "application/json",
+            r#"{"bookmark": null, "metadata": {"title": "Example Domain"}}"#.to_string(),
+        )]);
+        let cache_dir = tempfile::tempdir().unwrap();
+        let config: toml::Table = toml::from_str(&format!(
+            r#"
+            host = "{}"
+            api_key = "key"
+            debounce = 200
+            "#,
+            server.url
+        ))
+        .unwrap();
+        let mut source = LinkdingSource::new();
+        source.init(
+            &config,
+            Arc::new(Helpers::with_cache_dir(cache_dir.path().to_path_buf())),
+        );
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> =
+            Box::new(fuzzy_matcher::skim::SkimMatcherV2::default());
+        let create_item = |query| {
+            let item = source.search(query, &matcher).pop().unwrap();
+            (item.title.unwrap(), item.subtitle.unwrap())
+        };
+
+        for query in ["+ h", "+ https:", "+ https://exa", "+ https://example.com"] {
+            create_item(query);
+        }
+        let started = Instant::now();
+        while source.loading() {
+            assert!(started.elapsed() < Duration::from_secs(5), "still checking");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            create_item("+ https://example.com"),
+            (
+                "Save bookmark Example Domain".to_string(),
+                "https://example.com  (new)".to_string()
+            )
+        );
+        let checks: Vec<String> = server
+            .requests()
+            .into_iter()
+            .filter(|request| request.contains("/check/"))
+            .collect();
+        assert_eq!(
+            checks,
+            vec!["GET /api/bookmarks/check/?url=https%3A%2F%2Fexample.com"]
+        );
+
+        assert_eq!(
+            create_item("+ example.com #docs").1,
+            "example.com #docs (not an http(s) URL)"
+        );
+        assert!(!source.loading());
+    }
+}
@@ -19,6 +19,15 @@ pub use hstr::*;
 mod eval;
 pub use eval::*;
 
+mod calculator;
+pub use calculator::*;
+
+mod datetime;
+pub use datetime::*;
+
+mod currency;
+pub use currency::*;
+
 mod search_sites;
 pub use search_sites::*;
 
@@ -30,6 +39,9 @@ mod cliphist;
 #[cfg(feature = "cliphist")]
 pub use cliphist::*;
 
+mod clipboard;
+pub use clipboard::*;
+
 mod systemctl;
 pub use systemctl::*;
 
@@ -41,15 +53,27 @@ mod linkding;
 #[cfg(feature = "linkding")]
 pub use linkding::*;
 
+#[cfg(any(feature = "duckduckgo", feature = "searxng"))]
+mod paging;
+
 #[cfg(feature = "duckduckgo")]
 mod duckduckgo;
 #[cfg(feature = "duckduckgo")]
 pub use duckduckgo::*;
 
+#[cfg(feature = "searxng")]
+mod searxng;
+#[cfg(feature = "searxng")]
+pub use searxng::*;
+
 pub trait Source {
     fn name(&self) -> &'static str;
     fn init(&mut self, config: &toml::Table, helpers: Arc<Helpers>);
     fn deinit(&mut self);
     fn search(&self, query: &str, matcher: &Box<dyn FuzzyMatcher + Send + Sync>)
         -> Vec<SearchItem>;
+    /// Whether items are still arriving, UIs refresh their results until this returns false
+    fn loading(&self) -> bool {
+        false
+    }
 }
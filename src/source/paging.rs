@@ -0,0 +1,183 @@
+use log::warn;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// One fetched page and how to get the page after it
+pub struct Page<T, P> {
+    pub results: Vec<T>,
+    /// Passed to the fetch of the next page, `None` on the last page
+    pub next_page: Option<P>,
+}
+
+/// Results of one query, extended page by page
+#[derive(Clone)]
+pub struct PagedResults<T, P> {
+    pub results: Vec<T>,
+    pub next_page: Option<P>,
+    /// Whether a page is being fetched
+    pub fetching: bool,
+    /// Error of the last fetch, the results of earlier pages are kept
+    pub error: Option<String>,
+}
+
+impl<T, P> Default for PagedResults<T, P> {
+    fn default() -> Self {
+        Self {
+            results: vec![],
+            next_page: None,
+            fetching: false,
+            error: None,
+        }
+    }
+}
+
+/// Results of web searches by query, fetched page by page in separate threads.
+/// Typing another query goes back to a fresh search, typing the query again shows its results
+pub struct PagedSearches<T, P> {
+    searches: Mutex<HashMap<String, PagedResults<T, P>>>,
+}
+
+impl<T, P> PagedSearches<T, P>
+where
+    T: Clone + Send + 'static,
+    P: Clone + Send + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            searches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, query: &str) -> Option<PagedResults<T, P>> {
+        self.searches.lock().unwrap().get(query).cloned()
+    }
+
+    pub fn loading(&self) -> bool {
+        self.searches
+            .lock()
+            .unwrap()
+            .values()
+            .any(|search| search.fetching)
+    }
+
+    /// Fetches the first page of `query` in a separate thread, replacing its results,
+    /// or with `more` the page after the fetched ones.
+    /// Does nothing while a page of the query is fetched or if there is no next page,
+    /// so selecting "More results" twice fetches the page once
+    pub fn fetch<F>(self: &Arc<Self>, query: &str, more: bool, fetch: F)
+    where
+        F: FnOnce(Option<P>) -> Result<Page<T, P>, String> + Send + 'static,
+    {
+        let mut searches = self.searches.lock().unwrap();
+        let search = searches.entry(query.to_string()).or_default();
+        if search.fetching {
+            return;
+        }
+        let page = match (more, &search.next_page) {
+            (false, _) => None,
+            (true, Some(next_page)) => Some(next_page.clone()),
+            (true, None) => return,
+        };
+        search.fetching = true;
+        drop(searches);
+
+        let searches = self.clone();
+        let query = query.to_string();
+        std::thread::spawn(move || {
+            let fetched = fetch(page);
+            let mut searches = searches.searches.lock().unwrap();
+            let search = searches.entry(query).or_default();
+            search.fetching = false;
+            match fetched {
+                Ok(page) => {
+                    if !more {
+                        search.results.clear();
+                    }
+                    search.results.extend(page.results);
+                    search.next_page = page.next_page;
+                    search.error = None;
+                }
+                Err(err) => {
+                    warn!("Failed to fetch search results: {}", err);
+                    search.error = Some(err);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    fn wait_until_loaded(searches: &PagedSearches<u32, u32>) {
+        let started = Instant::now();
+        while searches.loading() {
+            assert!(started.elapsed() < Duration::from_secs(5), "still loading");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Returns pages `[page * 10, page * 10 + 1]`, up to page 3
+    fn numbers(page: Option<u32>) -> Result<Page<u32, u32>, String> {
+        let page = page.unwrap_or(1);
+        Ok(Page {
+            results: vec![page * 10, page * 10 + 1],
+            next_page: (page < 3).then_some(page + 1),
+        })
+    }
+
+    #[test]
+    fn appends_pages_until_the_last() {
+        let searches = Arc::new(PagedSearches::new());
+        searches.fetch("numbers", false, numbers);
+        wait_until_loaded(&searches);
+        searches.fetch("numbers", true, numbers);
+        wait_until_loaded(&searches);
+        searches.fetch("numbers", true, numbers);
+        wait_until_loaded(&searches);
+        let search = searches.get("numbers").unwrap();
+        assert_eq!(search.results, vec![10, 11, 20, 21, 30, 31]);
+        assert_eq!(search.next_page, None);
+
+        // Nothing left to fetch
+        searches.fetch("numbers", true, |_| panic!("fetched past the last page"));
+        assert!(!searches.loading());
+    }
+
+    #[test]
+    fn fetches_a_page_once_while_it_is_fetched() {
+        let searches = Arc::new(PagedSearches::new());
+        searches.fetch("numbers", false, numbers);
+        wait_until_loaded(&searches);
+
+        let (release, released) = mpsc::channel::<()>();
+        searches.fetch("numbers", true, move |page| {
+            released.recv().unwrap();
+            numbers(page)
+        });
+        assert!(searches.loading());
+        searches.fetch("numbers", true, |_| panic!("fetched the same page twice"));
+        release.send(()).unwrap();
+        wait_until_loaded(&searches);
+        assert_eq!(
+            searches.get("numbers").unwrap().results,
+            vec![10, 11, 20, 21]
+        );
+    }
+
+    #[test]
+    fn keeps_results_when_a_page_fails() {
+        let searches = Arc::new(PagedSearches::new());
+        searches.fetch("numbers", false, numbers);
+        wait_until_loaded(&searches);
+        searches.fetch("numbers", true, |_| Err("offline".to_string()));
+        wait_until_loaded(&searches);
+        let search = searches.get("numbers").unwrap();
+        assert_eq!(search.results, vec![10, 11]);
+        assert_eq!(search.next_page, Some(2));
+        assert_eq!(search.error.as_deref(), Some("offline"));
+    }
+}
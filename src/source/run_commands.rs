@@ -1,25 +1,307 @@
 use super::Source;
 use crate::helpers::Helpers;
-use crate::model::SearchItem;
+use crate::launcher::{self, LaunchContext};
+use crate::model::{ClipboardContent, SearchItem};
 
+use serde::Deserialize;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Captured lines beyond this are dropped
+const MAX_OUTPUT_LINES: usize = 1000;
+
+/// A command is either its command line or a table with more options
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CommandConfig {
+    Line(String),
+    Detailed(DetailedCommandConfig),
+}
+
+#[derive(Deserialize)]
+struct DetailedCommandConfig {
+    command: String,
+    description: Option<String>,
+    /// Names of the arguments, usable as `{name}` placeholders next to `{1}`, `{2}`, ...
+    #[serde(default)]
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    /// Ask before running, e.g. for destructive commands
+    #[serde(default)]
+    confirm: bool,
+    /// Show the lines of stdout as results instead of running detached, ignores `terminal`
+    #[serde(default)]
+    capture: bool,
+    /// Command line run with a selected output line as its last argument, in the same
+    /// working directory and environment. Without one the line is copied
+    follow_up: Option<String>,
+}
 
 struct RunCommand {
     command: String,
     run_in_terminal: bool,
+    description: Option<String>,
+    arg_names: Vec<String>,
+    context: LaunchContext,
+    confirm: bool,
+    capture: bool,
+    follow_up: Option<String>,
+}
+
+impl RunCommand {
+    fn from_config(config: CommandConfig, run_in_terminal: bool) -> Self {
+        let config = match config {
+            CommandConfig::Line(command) => DetailedCommandConfig {
+                command,
+                description: None,
+                args: vec![],
+                cwd: None,
+                env: HashMap::new(),
+                confirm: false,
+                capture: false,
+                follow_up: None,
+            },
+            CommandConfig::Detailed(config) => config,
+        };
+        let cwd = config.cwd.map(|cwd| match cwd.strip_prefix("~") {
+            Ok(rest) => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(rest))
+                .unwrap_or(cwd),
+            Err(_) => cwd,
+        });
+        Self {
+            command: config.command,
+            run_in_terminal,
+            description: config.description,
+            arg_names: config.args,
+            context: LaunchContext {
+                cwd,
+                env: config.env,
+            },
+            confirm: config.confirm,
+            capture: config.capture,
+            follow_up: config.follow_up,
+        }
+    }
+
+    /// Position of a placeholder like `{2}`, `{file}` or `{rest}` (0), `None` for other braces
+    fn placeholder_position(&self, name: &str) -> Option<usize> {
+        if name == "rest" {
+            return Some(0);
+        }
+        if let Ok(position) = name.parse::<usize>() {
+            return (position > 0).then_some(position);
+        }
+        self.arg_names
+            .iter()
+            .position(|arg_name| arg_name == name)
+            .map(|index| index + 1)
+    }
+
+    /// Splits a template argument into literal text and placeholder positions
+    fn parse_arg<'a>(&self, arg: &'a str) -> Vec<Result<&'a str, usize>> {
+        let mut parts = vec![];
+        let mut rest = arg;
+        while let Some(start) = rest.find('{') {
+            let Some(length) = rest[start..].find('}') else {
+                break;
+            };
+            let name = &rest[start + 1..start + length];
+            match self.placeholder_position(name) {
+                Some(position) => {
+                    parts.push(Ok(&rest[..start]));
+                    parts.push(Err(position));
+                }
+                None => parts.push(Ok(&rest[..start + length + 1])),
+            }
+            rest = &rest[start + length + 1..];
+        }
+        parts.push(Ok(rest));
+        parts
+    }
+
+    /// Describes the expected arguments, e.g. `<file> <message>` or `<1> [rest]`
+    fn usage(&self, template: &[String]) -> String {
+        let mut positions: Vec<usize> = template
+            .iter()
+            .flat_map(|arg| self.parse_arg(arg))
+            .filter_map(|part| part.err())
+            .collect();
+        positions.sort_unstable_by_key(|position| (*position == 0, *position));
+        positions.dedup();
+        positions
+            .into_iter()
+            .map(|position| {
+                if position == 0 {
+                    return "[rest]".to_string();
+                }
+                match self.arg_names.get(position - 1) {
+                    Some(name) => format!("<{}>", name),
+                    None => format!("<{}>", position),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Builds the arguments of the command from the text typed after its name.
+    /// Values are substituted after splitting the template, so they never need quoting.
+    /// A standalone `%s` expands to all typed arguments, like before placeholders existed
+    fn expand(&self, input: &str) -> Result<Vec<String>, String> {
+        let template = launcher::tokenize(&self.command).map_err(|err| err.to_string())?;
+        // Still unbalanced quotes while typing are taken literally
+        let values = shlex::split(input)
+            .unwrap_or_else(|| input.split_whitespace().map(str::to_string).collect());
+        let last_position = template
+            .iter()
+            .flat_map(|arg| self.parse_arg(arg))
+            .filter_map(|part| part.err())
+            .max()
+            .unwrap_or(0);
+        let rest = values
+            .get(last_position..)
+            .map(|rest| rest.join(" "))
+            .unwrap_or_default();
+        let mut args = vec![];
+        for arg in &template {
+            if arg == "%s" {
+                args.extend(values.iter().cloned());
+                continue;
+            }
+            if arg == "{rest}" && rest.is_empty() {
+                continue;
+            }
+            let mut expanded = String::new();
+            for part in self.parse_arg(arg) {
+                match part {
+                    Ok(text) => expanded.push_str(&text.replace("%s", input.trim())),
+                    Err(0) => expanded.push_str(&rest),
+                    Err(position) => match values.get(position - 1) {
+                        Some(value) => expanded.push_str(value),
+                        None => return Err(format!("Usage: {}", self.usage(&template))),
+                    },
+                }
+            }
+            args.push(expanded);
+        }
+        Ok(args)
+    }
+}
+
+struct RunCommandsSourceInner {
+    /// Query whose command was selected once and now waits for confirmation
+    confirming: Mutex<Option<String>>,
+    /// Lines printed by a captured command, by the query that ran it
+    outputs: Mutex<HashMap<String, Result<Vec<String>, String>>>,
+    /// Number of captured commands still running
+    capturing: AtomicUsize,
 }
 
 pub struct RunCommandsSource {
     pub commands: HashMap<String, RunCommand>,
+    inner: Arc<RunCommandsSourceInner>,
 }
 
 impl RunCommandsSource {
     pub fn new() -> Self {
         Self {
             commands: HashMap::new(),
+            inner: Arc::new(RunCommandsSourceInner {
+                confirming: Mutex::new(None),
+                outputs: Mutex::new(HashMap::new()),
+                capturing: AtomicUsize::new(0),
+            }),
         }
     }
+
+    /// Runs the command in a separate thread and shows its output for `query`
+    fn capture(
+        inner: Arc<RunCommandsSourceInner>,
+        query: String,
+        args: Vec<String>,
+        context: LaunchContext,
+    ) {
+        inner.capturing.fetch_add(1, Ordering::SeqCst);
+        std::thread::spawn(move || {
+            let output = launcher::capture(&args, &context)
+                .map(|stdout| {
+                    stdout
+                        .lines()
+                        .filter(|line| !line.trim().is_empty())
+                        .take(MAX_OUTPUT_LINES)
+                        .map(str::to_string)
+                        .collect()
+                })
+                .map_err(|err| err.to_string());
+            // Only the output of the latest run is kept
+            let mut outputs = inner.outputs.lock().unwrap();
+            outputs.clear();
+            outputs.insert(query, output);
+            inner.capturing.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    fn build_output_items(
+        &self,
+        run_command: &RunCommand,
+        output: Result<Vec<String>, String>,
+    ) -> Vec<SearchItem> {
+        let lines = match output {
+            Ok(lines) => lines,
+            Err(err) => return vec![SearchItem::error(err)],
+        };
+        let count = lines.len();
+        lines
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| {
+                let follow_up = run_command.follow_up.as_ref().map(|follow_up| {
+                    launcher::tokenize(follow_up)
+                        .map(|mut args| {
+                            args.push(line.clone());
+                            args
+                        })
+                        .map_err(|err| err.to_string())
+                });
+                let subtitle = match &follow_up {
+                    Some(Ok(args)) => shlex::try_join(args.iter().map(String::as_str))
+                        .unwrap_or_else(|_| args.join(" ")),
+                    Some(Err(err)) => err.clone(),
+                    None => "Copy to clipboard".to_string(),
+                };
+                let context = run_command.context.clone();
+                SearchItem {
+                    id: format!("{}:output:{}", self.name(), line),
+                    title: Some(line.clone()),
+                    subtitle: Some(subtitle),
+                    icon: None,
+                    image: None,
+                    // Keeps the order of the output
+                    score: (count - index) as i64,
+                    source: self.name(),
+                    layer: crate::model::ItemLayer::Top,
+                    action: Rc::new(move |os| {
+                        if let Some(follow_up) = &follow_up {
+                            let result = match follow_up {
+                                Ok(args) => os
+                                    .run_command(args, false, &context)
+                                    .map_err(|err| err.to_string()),
+                                Err(err) => Err(err.clone()),
+                            };
+                            return os.report(result);
+                        }
+                        let result = os.copy_to_clipboard(ClipboardContent::text(line.clone()));
+                        os.report(result)
+                    }),
+                }
+            })
+            .collect()
+    }
 }
 
 impl Source for RunCommandsSource {
@@ -28,51 +310,53 @@ impl Source for RunCommandsSource {
     }
 
     fn init(&mut self, config: &toml::Table, _helpers: Arc<Helpers>) {
-        let default_commands: HashMap<String, String> = config
+        let default_commands: HashMap<String, CommandConfig> = config
             .get("default")
             .map_or(toml::Value::Table(toml::Table::new()), |value| {
                 value.to_owned()
             })
             .try_into()
             .unwrap();
-        let terminal_commands: HashMap<String, String> = config
+        let terminal_commands: HashMap<String, CommandConfig> = config
             .get("terminal")
             .map_or(toml::Value::Table(toml::Table::new()), |value| {
                 value.to_owned()
             })
             .try_into()
             .unwrap();
-        let default_commands_iter = default_commands.into_iter().map(|(name, command)| {
-            (
-                name,
-                RunCommand {
-                    command: command.clone(),
-                    run_in_terminal: false,
-                },
-            )
-        });
-        let terminal_commands_iter = terminal_commands.into_iter().map(|(name, command)| {
-            (
-                name,
-                RunCommand {
-                    command: command.clone(),
-                    run_in_terminal: true,
-                },
-            )
-        });
+        let default_commands_iter = default_commands
+            .into_iter()
+            .map(|(name, command)| (name, RunCommand::from_config(command, false)));
+        let terminal_commands_iter = terminal_commands
+            .into_iter()
+            .map(|(name, command)| (name, RunCommand::from_config(command, true)));
         let commands = default_commands_iter
             .chain(terminal_commands_iter)
             .collect();
         self.commands = commands;
     }
 
-    fn deinit(&mut self) {}
+    fn loading(&self) -> bool {
+        self.inner.capturing.load(Ordering::SeqCst) > 0
+    }
+
+    fn deinit(&mut self) {
+        self.inner.outputs.lock().unwrap().clear();
+    }
 
     fn search(
         &self,
         query: &str,
         _matcher: &Box<dyn fuzzy_matcher::FuzzyMatcher + Send + Sync>,
     ) -> Vec<crate::model::SearchItem> {
+        // A confirmation only holds while the query stays the same
+        let confirmed = {
+            let mut confirming = self.inner.confirming.lock().unwrap();
+            if confirming.as_deref() != Some(query) {
+                *confirming = None;
+            }
+            confirming.is_some()
+        };
         let (left, right) = match query.split_once(" ") {
             Some((left, right)) => (left, right),
             None => (query.trim_end(), ""),
@@ -81,27 +365,360 @@ impl Source for RunCommandsSource {
             return vec![];
         }
         let run_command = self.commands.get(left).unwrap();
-        let command_template = &run_command.command;
         let run_in_terminal = run_command.run_in_terminal;
-        let command = command_template.replace("%s", right);
-        let item = SearchItem {
+        let args = run_command.expand(right);
+        let command_line = match &args {
+            Ok(args) => {
+                shlex::try_join(args.iter().map(String::as_str)).unwrap_or_else(|_| args.join(" "))
+            }
+            Err(usage) => format!("{} {}", left, usage.trim_start_matches("Usage: ")),
+        };
+        let awaiting_confirmation = run_command.confirm && !confirmed;
+        let title = if run_command.confirm && !awaiting_confirmation {
+            format!("Confirm: run {}?", command_line)
+        } else {
+            match &run_command.description {
+                Some(description) => description.clone(),
+                None => format!("Run command {}", right),
+            }
+        };
+        let subtitle = match &args {
+            Err(usage) => usage.clone(),
+            Ok(_) if awaiting_confirmation => format!("{} (asks to confirm)", command_line),
+            Ok(_) => command_line,
+        };
+        let output = self.inner.outputs.lock().unwrap().get(query).cloned();
+        let mut items = match output {
+            Some(output) => self.build_output_items(run_command, output),
+            None => vec![],
+        };
+        let context = run_command.context.clone();
+        let capture = run_command.capture;
+        let inner = self.inner.clone();
+        let query = query.to_string();
+        items.push(SearchItem {
             id: self.name().to_string() + &left,
-            title: Some(format!("Run command {}", right)),
-            subtitle: Some(command.clone()),
+            title: Some(title),
+            subtitle: Some(subtitle),
             icon: None,
             image: None,
             score: 100,
             source: self.name(),
             layer: crate::model::ItemLayer::Middle,
-            action: Box::new(move |os| {
-                if run_in_terminal {
-                    os.run_in_terminal(&command);
-                } else {
-                    os.run(&command);
+            action: Rc::new(move |os| {
+                let args = match &args {
+                    Ok(args) => args,
+                    Err(usage) => return os.report(Err(usage.clone())),
+                };
+                // The first selection only asks, keeping the query
+                if awaiting_confirmation {
+                    *inner.confirming.lock().unwrap() = Some(query.clone());
+                    os.set_query(query.clone());
+                    return false;
+                }
+                *inner.confirming.lock().unwrap() = None;
+                // Captured output is listed in place of the query
+                if capture {
+                    Self::capture(inner.clone(), query.clone(), args.clone(), context.clone());
+                    os.set_query(query.clone());
+                    return false;
                 }
-                true
+                let result = os.run_command(args, run_in_terminal, &context);
+                os.report(result)
             }),
+        });
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opts::Config;
+    use crate::os::Os;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+    use fuzzy_matcher::FuzzyMatcher;
+    use std::time::{Duration, Instant};
+
+    fn source(config: &str) -> RunCommandsSource {
+        let mut source = RunCommandsSource::new();
+        source.init(
+            &toml::from_str(config).unwrap(),
+            Arc::new(Helpers::default()),
+        );
+        source
+    }
+
+    fn search(source: &RunCommandsSource, query: &str) -> Vec<(String, String)> {
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        source
+            .search(query, &matcher)
+            .into_iter()
+            .map(|item| {
+                (
+                    item.title.unwrap_or_default(),
+                    item.subtitle.unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    /// Runs the command of `query` like selecting it does and waits for its output
+    fn capture(source: &RunCommandsSource, query: &str) {
+        let (name, input) = query.split_once(' ').unwrap_or((query, ""));
+        let run_command = &source.commands[name];
+        let args = run_command.expand(input).unwrap();
+        let context = run_command.context.clone();
+        RunCommandsSource::capture(source.inner.clone(), query.to_string(), args, context);
+        let started = Instant::now();
+        while source.loading() {
+            assert!(started.elapsed() < Duration::from_secs(5), "still running");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn expands_placeholders_into_single_arguments() {
+        let source = source(
+            r#"
+            [default]
+            echo = "echo %s"
+            open = "xdg-open https://example.com/?q=%s"
+
+            [default.commit]
+            command = "git commit -m {message} {rest}"
+            args = ["message"]
+
+            [default.copy]
+            command = "cp {1} {2}"
+            "#,
+        );
+        let expand = |name: &str, input: &str| source.commands[name].expand(input);
+        assert_eq!(
+            expand("echo", "a 'b c'"),
+            Ok(vec!["echo".to_string(), "a".to_string(), "b c".to_string()])
+        );
+        assert_eq!(
+            expand("open", "rust book"),
+            Ok(vec![
+                "xdg-open".to_string(),
+                "https://example.com/?q=rust book".to_string()
+            ])
+        );
+        assert_eq!(
+            expand("commit", "'fix: it; rm -rf ~' --amend"),
+            Ok(vec![
+                "git".to_string(),
+                "commit".to_string(),
+                "-m".to_string(),
+                "fix: it; rm -rf ~".to_string(),
+                "--amend".to_string(),
+            ])
+        );
+        // An empty {rest} is dropped instead of passing an empty argument
+        assert_eq!(expand("commit", "wip").unwrap().len(), 4);
+        // Unbalanced quotes while typing are taken literally
+        assert_eq!(
+            expand("copy", "it's here"),
+            Ok(vec![
+                "cp".to_string(),
+                "it's".to_string(),
+                "here".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn missing_arguments_show_the_usage() {
+        let source = source(
+            r#"
+            [default.commit]
+            command = "git commit -m {message} {rest}"
+            args = ["message"]
+
+            [default.copy]
+            command = "cp {1} {2}"
+            "#,
+        );
+        assert_eq!(
+            source.commands["commit"].expand(""),
+            Err("Usage: <message> [rest]".to_string())
+        );
+        assert_eq!(
+            source.commands["copy"].expand("a"),
+            Err("Usage: <1> <2>".to_string())
+        );
+        assert_eq!(
+            search(&source, "copy a"),
+            vec![("Run command a".to_string(), "Usage: <1> <2>".to_string())]
+        );
+    }
+
+    #[test]
+    fn captured_lines_become_items() {
+        let source = source(
+            r#"
+            [default.count]
+            command = "seq {1}"
+            capture = true
+            follow_up = "open"
+            "#,
+        );
+        capture(&source, "count 2");
+        let items = search(&source, "count 2");
+        assert_eq!(
+            items,
+            vec![
+                ("1".to_string(), "open 1".to_string()),
+                ("2".to_string(), "open 2".to_string()),
+                ("Run command 2".to_string(), "seq 2".to_string()),
+            ]
+        );
+        // Another query runs the command again
+        assert_eq!(search(&source, "count 3").len(), 1);
+    }
+
+    #[test]
+    fn follow_ups_run_with_the_selected_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&format!(
+            r#"
+            [default.pick]
+            command = "sh -c 'echo one; echo two words'"
+            capture = true
+            follow_up = '''sh -c 'printf %s "$1" > "$PICKED"' follow-up'''
+            cwd = "{}"
+            env = {{ PICKED = "picked" }}
+            "#,
+            dir.path().display()
+        ));
+        capture(&source, "pick");
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        let item = source
+            .search("pick", &matcher)
+            .into_iter()
+            .find(|item| item.title.as_deref() == Some("two words"))
+            .unwrap();
+        assert!(item
+            .subtitle
+            .as_ref()
+            .unwrap()
+            .ends_with("follow-up 'two words'"));
+
+        let data = tempfile::tempdir().unwrap();
+        let mut os = Os::with_data_dir(Config::default(), data.path());
+        assert!(os.select(&item));
+        let picked = dir.path().join("picked");
+        let started = Instant::now();
+        while std::fs::read_to_string(&picked).unwrap_or_default() != "two words" {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "follow-up did not run"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn confirms_before_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&format!(
+            r#"
+            [default.reset]
+            command = "touch reset"
+            confirm = true
+            cwd = "{}"
+            "#,
+            dir.path().display()
+        ));
+        let data = tempfile::tempdir().unwrap();
+        let mut os = Os::with_data_dir(Config::default(), data.path());
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        let select = |os: &mut Os, query| {
+            let item = source.search(query, &matcher).pop().unwrap();
+            os.select(&item)
         };
-        return vec![item];
+        let asking = vec![(
+            "Run command ".to_string(),
+            "touch reset (asks to confirm)".to_string(),
+        )];
+        let confirming = vec![(
+            "Confirm: run touch reset?".to_string(),
+            "touch reset".to_string(),
+        )];
+        assert_eq!(search(&source, "reset"), asking);
+        assert!(!select(&mut os, "reset"));
+        assert_eq!(os.take_query().as_deref(), Some("reset"));
+        assert_eq!(search(&source, "reset"), confirming);
+
+        // Changing the query asks again
+        search(&source, "reset ");
+        assert_eq!(search(&source, "reset"), asking);
+        assert!(!select(&mut os, "reset"));
+        assert!(!dir.path().join("reset").exists());
+
+        assert!(select(&mut os, "reset"));
+        let started = Instant::now();
+        while !dir.path().join("reset").exists() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "command did not run"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(search(&source, "reset"), asking);
+    }
+
+    #[test]
+    fn runs_in_the_configured_directory_and_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = source(&format!(
+            r#"
+            [default.where]
+            command = "pwd"
+            capture = true
+            cwd = "{}"
+
+            [default.home]
+            command = "pwd"
+            capture = true
+            cwd = "~"
+
+            [default.greet]
+            command = "sh -c 'echo \"$GREETING, $1\"' greet {{1}}"
+            capture = true
+            env = {{ GREETING = "Hello" }}
+            "#,
+            dir.path().display()
+        ));
+        let lines = |query| {
+            capture(&source, query);
+            let mut items = search(&source, query);
+            items.pop();
+            items
+                .into_iter()
+                .map(|(title, _)| title)
+                .collect::<Vec<String>>()
+        };
+        let canonical = |path: PathBuf| path.canonicalize().unwrap().display().to_string();
+        assert_eq!(lines("where"), [canonical(dir.path().to_path_buf())]);
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap());
+        assert_eq!(lines("home"), [canonical(home)]);
+        assert_eq!(lines("greet world"), ["Hello, world"]);
+    }
+
+    #[test]
+    fn failed_captures_show_an_error() {
+        let source = source(
+            r#"
+            [default.fail]
+            command = "false"
+            capture = true
+            "#,
+        );
+        capture(&source, "fail");
+        let items = search(&source, "fail");
+        assert_eq!(items[0].0, "Error");
+        assert!(items[0].1.starts_with("false failed"));
     }
 }
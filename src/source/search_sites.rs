@@ -2,19 +2,155 @@ use super::Source;
 use crate::helpers::Helpers;
 use crate::model::SearchItem;
 
+use log::warn;
+use serde::Deserialize;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::Arc;
 
-pub struct SearchSitesSource {
+/// Keyword suggestions shown while the keyword is typed
+const MAX_SUGGESTIONS: usize = 20;
+
+#[derive(Deserialize)]
+pub struct SearchSitesConfig {
+    /// Site searched when the query does not start with a keyword, either a keyword or a URL
+    pub default: Option<String>,
+    /// DuckDuckGo `!bang` definitions, e.g. a downloaded https://duckduckgo.com/bang.js
+    pub bangs_file: Option<PathBuf>,
+    /// Keywords mapped to URLs, `%s` is replaced by the query
+    #[serde(default)]
     pub sites: HashMap<String, String>,
+    /// Keywords given next to the options, as before `sites` existed.
+    /// Keywords named like an option can only be given in `sites`
+    #[serde(flatten)]
+    pub top_level_sites: HashMap<String, String>,
+}
+
+/// Entry of the DuckDuckGo bang list, e.g.
+/// `{"t": "gh", "s": "GitHub", "u": "https://github.com/search?q={{{s}}}"}`
+#[derive(Deserialize)]
+struct Bang {
+    #[serde(rename = "t")]
+    keyword: String,
+    #[serde(rename = "s", default)]
+    name: String,
+    #[serde(rename = "u")]
+    url: String,
+}
+
+#[derive(Clone)]
+pub struct SearchSite {
+    pub name: String,
+    pub url: String,
+}
+
+impl SearchSite {
+    fn search_url(&self, query: &str) -> String {
+        self.url.replace("%s", &urlencoding::encode(query))
+    }
+}
+
+pub struct SearchSitesSource {
+    pub sites: HashMap<String, SearchSite>,
+    default: Option<SearchSite>,
 }
 
 impl SearchSitesSource {
     pub fn new() -> Self {
         Self {
             sites: HashMap::new(),
+            default: None,
         }
     }
+
+    fn read_bangs(path: &Path) -> Vec<Bang> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                warn!("Failed to read bangs from {}: {}", path.display(), err);
+                return vec![];
+            }
+        };
+        match serde_json::from_str(&content) {
+            Ok(bangs) => bangs,
+            Err(err) => {
+                warn!("Invalid bangs in {}: {}", path.display(), err);
+                vec![]
+            }
+        }
+    }
+
+    /// Keywords may be typed with a leading `!` like bangs
+    fn site(&self, keyword: &str) -> Option<&SearchSite> {
+        let keyword = keyword.strip_prefix('!').unwrap_or(keyword);
+        self.sites.get(keyword)
+    }
+
+    fn build_item(&self, keyword: &str, site: &SearchSite, query: &str, score: i64) -> SearchItem {
+        let search_url = site.search_url(query);
+        SearchItem {
+            id: self.name().to_string() + keyword,
+            title: Some(format!("Search {} for {}", site.name, query)),
+            subtitle: Some(search_url.clone()),
+            icon: None,
+            image: None,
+            score,
+            source: self.name(),
+            layer: crate::model::ItemLayer::Middle,
+            action: Rc::new(move |os| {
+                let result = os.open_url(&search_url);
+                os.report(result)
+            }),
+        }
+    }
+
+    /// Lists keywords matching the first word while it is typed, selecting one completes it
+    fn build_suggestions(
+        &self,
+        word: &str,
+        matcher: &(dyn fuzzy_matcher::FuzzyMatcher + Send + Sync),
+    ) -> Vec<SearchItem> {
+        let typed = word.strip_prefix('!').unwrap_or(word);
+        if typed.is_empty() {
+            return vec![];
+        }
+        let mut matches: Vec<(i64, &String, &SearchSite)> = self
+            .sites
+            .iter()
+            .filter_map(|(keyword, site)| {
+                let score = matcher.fuzzy_match(keyword, typed)?;
+                Some((score, keyword, site))
+            })
+            .collect();
+        // Shorter keywords first among equal matches, they are what is typed so far
+        matches.sort_by(|(a_score, a, _), (b_score, b, _)| {
+            b_score.cmp(a_score).then(a.len().cmp(&b.len()))
+        });
+        let prefix = if word.starts_with('!') { "!" } else { "" };
+        matches
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .enumerate()
+            .map(|(index, (_, keyword, site))| {
+                let completed = format!("{}{} ", prefix, keyword);
+                SearchItem {
+                    id: format!("{}:keyword:{}", self.name(), keyword),
+                    title: Some(format!("{}{}", prefix, keyword)),
+                    subtitle: Some(format!("Search {}", site.name)),
+                    icon: None,
+                    image: None,
+                    score: (MAX_SUGGESTIONS - index) as i64,
+                    source: self.name(),
+                    layer: crate::model::ItemLayer::Bottom,
+                    action: Rc::new(move |os| {
+                        os.set_query(completed.clone());
+                        false
+                    }),
+                }
+            })
+            .collect()
+    }
 }
 
 impl Source for SearchSitesSource {
@@ -23,7 +159,40 @@ impl Source for SearchSitesSource {
     }
 
     fn init(&mut self, config: &toml::Table, _helpers: Arc<Helpers>) {
-        self.sites = config.clone().try_into().unwrap();
+        let config: SearchSitesConfig = config.clone().try_into().unwrap();
+        if let Some(path) = &config.bangs_file {
+            for bang in Self::read_bangs(path) {
+                let name = if bang.name.is_empty() {
+                    bang.keyword.clone()
+                } else {
+                    bang.name
+                };
+                let url = bang.url.replace("{{{s}}}", "%s");
+                self.sites.insert(bang.keyword, SearchSite { name, url });
+            }
+        }
+        // Configured sites win over imported bangs
+        for (keyword, url) in config.top_level_sites.into_iter().chain(config.sites) {
+            let name = keyword.clone();
+            self.sites.insert(keyword, SearchSite { name, url });
+        }
+        self.default = config.default.and_then(|default| {
+            if default.contains("%s") {
+                let domain = default
+                    .split("://")
+                    .nth(1)
+                    .and_then(|rest| rest.split('/').next());
+                return Some(SearchSite {
+                    name: domain.unwrap_or(&default).to_string(),
+                    url: default,
+                });
+            }
+            let site = self.site(&default).cloned();
+            if site.is_none() {
+                warn!("No search site '{}' to use as default", default);
+            }
+            site
+        });
     }
 
     fn deinit(&mut self) {}
@@ -31,30 +200,179 @@ impl Source for SearchSitesSource {
     fn search(
         &self,
         query: &str,
-        _matcher: &Box<dyn fuzzy_matcher::FuzzyMatcher + Send + Sync>,
+        matcher: &Box<dyn fuzzy_matcher::FuzzyMatcher + Send + Sync>,
     ) -> Vec<crate::model::SearchItem> {
-        if let Some((left, right)) = query.split_once(" ") {
-            if !self.sites.contains_key(left) {
-                return vec![];
+        let query = query.trim_start();
+        if query.is_empty() {
+            return vec![];
+        }
+        let mut items = vec![];
+        match query.split_once(' ') {
+            Some((keyword, rest)) if self.site(keyword).is_some() => {
+                let site = self.site(keyword).unwrap();
+                let keyword = keyword.trim_start_matches('!');
+                if !rest.trim().is_empty() {
+                    items.push(self.build_item(keyword, site, rest.trim(), 100));
+                }
+                return items;
             }
-            let url = self.sites.get(left).unwrap();
-            let search_url = url.replace("%s", right);
-            let item = SearchItem {
-                id: self.name().to_string() + &left,
-                title: Some(format!("Search for {}", right)),
-                subtitle: Some(search_url.clone()),
-                icon: None,
-                image: None,
-                score: 100,
-                source: self.name(),
-                layer: crate::model::ItemLayer::Middle,
-                action: Box::new(move |os| {
-                    os.open_url(&search_url);
-                    true
-                }),
-            };
-            return vec![item];
-        }
-        return vec![];
+            Some(_) => {}
+            None => items.extend(self.build_suggestions(query, matcher.as_ref())),
+        }
+        if let Some(default) = &self.default {
+            items.push(self.build_item("default", default, query.trim(), 0));
+        }
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+    use fuzzy_matcher::FuzzyMatcher;
+
+    fn init(config: &str) -> SearchSitesSource {
+        let dir = tempfile::tempdir().unwrap();
+        let helpers = Arc::new(Helpers::with_cache_dir(dir.path().to_path_buf()));
+        let mut source = SearchSitesSource::new();
+        source.init(&toml::from_str(config).unwrap(), helpers);
+        source
+    }
+
+    /// Titles and subtitles of the items found for `query`
+    fn search(source: &SearchSitesSource, query: &str) -> Vec<(String, String)> {
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        source
+            .search(query, &matcher)
+            .into_iter()
+            .map(|item| (item.title.unwrap(), item.subtitle.unwrap()))
+            .collect()
+    }
+
+    fn urls(source: &SearchSitesSource, query: &str) -> Vec<String> {
+        search(source, query)
+            .into_iter()
+            .map(|(_, subtitle)| subtitle)
+            .collect()
+    }
+
+    #[test]
+    fn encodes_queries() {
+        let source = init(r#"g = "https://g.test/search?q=%s""#);
+        let cases = [
+            ("g rust", "https://g.test/search?q=rust"),
+            (
+                "g fish & chips",
+                "https://g.test/search?q=fish%20%26%20chips",
+            ),
+            ("g #1 hit", "https://g.test/search?q=%231%20hit"),
+            ("g a=b?c/d", "https://g.test/search?q=a%3Db%3Fc%2Fd"),
+            ("!g  spaced ", "https://g.test/search?q=spaced"),
+        ];
+        for (query, url) in cases {
+            assert_eq!(urls(&source, query), [url], "{}", query);
+        }
+        assert!(search(&source, "g ").is_empty());
+    }
+
+    #[test]
+    fn suggests_keywords_while_typed() {
+        let source = init(
+            r#"
+            g = "https://g.test/?q=%s"
+            gh = "https://gh.test/?q=%s"
+            wiki = "https://wiki.test/%s"
+            "#,
+        );
+        let titles = |query| {
+            search(&source, query)
+                .into_iter()
+                .map(|(title, _)| title)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(titles("wi"), ["wiki"]);
+        assert_eq!(titles("!wi"), ["!wiki"]);
+        assert_eq!(titles("g"), ["g", "gh"]);
+        assert!(titles("xyz").is_empty());
+        assert_eq!(
+            search(&source, "gh"),
+            [("gh".to_string(), "Search gh".to_string())]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_default_site() {
+        let source = init(
+            r#"
+            default = "default"
+            g = "https://old.test/?q=%s"
+
+            [sites]
+            default = "https://default.test/?q=%s"
+            g = "https://g.test/?q=%s"
+            "#,
+        );
+        assert_eq!(urls(&source, "g rust"), ["https://g.test/?q=rust"]);
+        assert_eq!(
+            urls(&source, "rust lang"),
+            ["https://default.test/?q=rust%20lang"]
+        );
+        assert_eq!(
+            urls(&source, "default rust"),
+            ["https://default.test/?q=rust"]
+        );
+
+        let source = init(r#"default = "https://www.example.org/search?q=%s""#);
+        assert_eq!(
+            search(&source, "rust lang"),
+            [(
+                "Search www.example.org for rust lang".to_string(),
+                "https://www.example.org/search?q=rust%20lang".to_string()
+            )]
+        );
+
+        let source = init(r#"default = "missing""#);
+        assert!(search(&source, "rust lang").is_empty());
+    }
+
+    #[test]
+    fn imports_bangs() {
+        let dir = tempfile::tempdir().unwrap();
+        let bangs_file = dir.path().join("bang.js");
+        std::fs::write(
+            &bangs_file,
+            r#"[
+                {"t": "gh", "s": "GitHub", "u": "https://github.com/search?q={{{s}}}"},
+                {"t": "w", "u": "https://en.wikipedia.org/wiki/{{{s}}}"},
+                {"t": "g", "s": "Google", "u": "https://www.google.com/search?q={{{s}}}"}
+            ]"#,
+        )
+        .unwrap();
+        let source = init(&format!(
+            r#"
+            bangs_file = "{}"
+            g = "https://g.test/?q=%s"
+            "#,
+            bangs_file.display()
+        ));
+        assert_eq!(
+            search(&source, "!gh rust lang"),
+            [(
+                "Search GitHub for rust lang".to_string(),
+                "https://github.com/search?q=rust%20lang".to_string()
+            )]
+        );
+        assert_eq!(
+            search(&source, "w Rust"),
+            [(
+                "Search w for Rust".to_string(),
+                "https://en.wikipedia.org/wiki/Rust".to_string()
+            )]
+        );
+        assert_eq!(urls(&source, "!g rust"), ["https://g.test/?q=rust"]);
+
+        let missing = init(r#"bangs_file = "/nonexistent/bang.js""#);
+        assert!(missing.sites.is_empty());
     }
 }
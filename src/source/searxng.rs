@@ -0,0 +1,517 @@
+use super::paging::{Page, PagedResults, PagedSearches};
+use super::Source;
+use crate::helpers::Helpers;
+use crate::model::{ItemLayer, OSImage, SearchItem};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::io::Read;
+use std::rc::Rc;
+use std::sync::Arc;
+use ureq::get;
+
+/// Categories of a default SearXNG instance, other `!selectors` are taken as engines
+const CATEGORIES: &[&str] = &[
+    "general",
+    "images",
+    "videos",
+    "news",
+    "map",
+    "music",
+    "it",
+    "science",
+    "files",
+    "social_media",
+];
+
+/// Downloaded icons and thumbnails larger than this are skipped
+const MAX_IMAGE_SIZE: u64 = 5 * 1024 * 1024;
+
+const fn _default_icons() -> bool {
+    true
+}
+
+fn _default_user_agent() -> String {
+    format!("{}/{}", crate::APP_NAME, env!("CARGO_PKG_VERSION"))
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SearxngConfig {
+    /// Base URL of the instance, which needs the `json` format enabled in its settings
+    pub url: String,
+    /// Searched when the query has no `!category` selector
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Searched when the query has no `!engine` selector
+    #[serde(default)]
+    pub engines: Vec<String>,
+    pub language: Option<String>,
+    /// Load favicons and thumbnails
+    #[serde(default = "_default_icons")]
+    pub icons: bool,
+    /// Favicon service with a `{domain}` placeholder, e.g.
+    /// "https://www.google.com/s2/favicons?domain={domain}&sz=32"
+    pub favicon_url: Option<String>,
+    #[serde(default = "_default_user_agent")]
+    pub user_agent: String,
+}
+
+#[derive(Deserialize)]
+struct SearxngResponse {
+    results: Vec<SearxngResult>,
+}
+
+#[derive(Deserialize)]
+struct SearxngResult {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    engines: Vec<String>,
+    #[serde(default)]
+    thumbnail: Option<String>,
+    #[serde(default)]
+    thumbnail_src: Option<String>,
+    #[serde(default)]
+    img_src: Option<String>,
+}
+
+impl SearxngResult {
+    /// Image results carry the image itself, other results may have a thumbnail
+    fn image_url(&self) -> Option<&str> {
+        let urls = [&self.thumbnail_src, &self.img_src, &self.thumbnail];
+        urls.into_iter()
+            .flatten()
+            .map(|url| url.as_str())
+            .find(|url| !url.is_empty())
+    }
+
+    fn domain(&self) -> Option<&str> {
+        let rest = self.url.split_once("://")?.1;
+        rest.split(['/', '?', '#']).next()
+    }
+}
+
+/// `!name` words select categories or engines, the rest is searched
+#[derive(Default)]
+struct SearxngQuery {
+    text: String,
+    categories: Vec<String>,
+    engines: Vec<String>,
+}
+
+impl SearxngQuery {
+    fn parse(query: &str) -> Self {
+        let mut parsed = Self::default();
+        let mut words = vec![];
+        for word in query.split_whitespace() {
+            match word.strip_prefix('!').filter(|name| !name.is_empty()) {
+                Some(name) if CATEGORIES.contains(&name) => {
+                    parsed.categories.push(name.to_string())
+                }
+                Some(name) => parsed.engines.push(name.to_string()),
+                None => words.push(word),
+            }
+        }
+        parsed.text = words.join(" ");
+        parsed
+    }
+}
+
+#[derive(Clone)]
+struct SearxngHit {
+    title: String,
+    url: String,
+    content: String,
+    engines: Vec<String>,
+    icon: Option<OSImage>,
+    image: Option<OSImage>,
+}
+
+pub struct SearxngSource {
+    /// Hits by query, paged by page number
+    searches: Arc<PagedSearches<SearxngHit, u32>>,
+    config: Option<SearxngConfig>,
+}
+
+impl SearxngSource {
+    pub fn new() -> Self {
+        Self {
+            searches: Arc::new(PagedSearches::new()),
+            config: None,
+        }
+    }
+
+    fn fetch_image(config: &SearxngConfig, url: &str) -> Option<OSImage> {
+        // Protocol relative URLs are common in image results
+        let url = match url.strip_prefix("//") {
+            Some(rest) => format!("https://{}", rest),
+            None => url.to_string(),
+        };
+        let response = get(&url)
+            .set("User-Agent", &config.user_agent)
+            .call()
+            .ok()?;
+        let mut bytes = vec![];
+        response
+            .into_reader()
+            .take(MAX_IMAGE_SIZE)
+            .read_to_end(&mut bytes)
+            .ok()?;
+        Helpers::decode_image(bytes)
+    }
+
+    fn fetch_page(
+        config: &SearxngConfig,
+        query: &SearxngQuery,
+        page: u32,
+    ) -> Result<Page<SearxngHit, u32>, String> {
+        let categories = if query.categories.is_empty() {
+            &config.categories
+        } else {
+            &query.categories
+        };
+        let engines = if query.engines.is_empty() {
+            &config.engines
+        } else {
+            &query.engines
+        };
+        let mut request = get(&format!("{}/search", config.url.trim_end_matches('/')))
+            .set("User-Agent", &config.user_agent)
+            .query("q", &query.text)
+            .query("format", "json")
+            .query("pageno", &page.to_string());
+        if !categories.is_empty() {
+            request = request.query("categories", &categories.join(","));
+        }
+        if !engines.is_empty() {
+            request = request.query("engines", &engines.join(","));
+        }
+        if let Some(language) = &config.language {
+            request = request.query("language", language);
+        }
+        let response: SearxngResponse = request
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())?;
+        let hits = response
+            .results
+            .into_par_iter()
+            .map(|result| {
+                let is_image = result.category.as_deref() == Some("images");
+                let thumbnail = result
+                    .image_url()
+                    .filter(|_| config.icons)
+                    .and_then(|url| Self::fetch_image(config, url));
+                let favicon = config
+                    .favicon_url
+                    .as_ref()
+                    .zip(result.domain())
+                    .filter(|_| config.icons)
+                    .and_then(|(template, domain)| {
+                        Self::fetch_image(config, &template.replace("{domain}", domain))
+                    });
+                let (icon, image) = if is_image {
+                    (favicon, thumbnail)
+                } else {
+                    (favicon.or(thumbnail), None)
+                };
+                SearxngHit {
+                    title: result.title,
+                    url: result.url,
+                    content: result.content,
+                    engines: result.engines,
+                    icon,
+                    image,
+                }
+            })
+            .collect::<Vec<SearxngHit>>();
+        // SearXNG does not tell whether there are more pages, an empty one is the last
+        let next_page = (!hits.is_empty()).then_some(page + 1);
+        Ok(Page {
+            results: hits,
+            next_page,
+        })
+    }
+
+    /// Fetches the first page of the query in a separate thread, or with `more` the next one
+    fn fetch(
+        searches: &Arc<PagedSearches<SearxngHit, u32>>,
+        config: &SearxngConfig,
+        query: &str,
+        more: bool,
+    ) {
+        let config = config.clone();
+        let parsed = SearxngQuery::parse(query);
+        searches.fetch(query, more, move |page| {
+            Self::fetch_page(&config, &parsed, page.unwrap_or(1))
+        });
+    }
+
+    /// Searches in a separate thread, keeping the launcher open with the query
+    fn search_action(&self, query: String, more: bool) -> Rc<dyn Fn(&mut crate::os::Os) -> bool> {
+        let searches = self.searches.clone();
+        let config = self.config.clone().unwrap();
+        Rc::new(move |os| {
+            Self::fetch(&searches, &config, &query, more);
+            os.set_query(query.clone());
+            false
+        })
+    }
+
+    fn build_search_item(
+        &self,
+        query: &str,
+        title: &str,
+        subtitle: String,
+        more: bool,
+    ) -> SearchItem {
+        SearchItem {
+            id: if more {
+                format!("{}:more", self.name())
+            } else {
+                self.name().to_string()
+            },
+            title: Some(title.to_string()),
+            subtitle: Some(subtitle),
+            icon: None,
+            image: None,
+            score: 0,
+            source: self.name(),
+            action: self.search_action(query.to_string(), more),
+            layer: ItemLayer::Top,
+        }
+    }
+
+    fn build_result_items(
+        &self,
+        query: &str,
+        searched: PagedResults<SearxngHit, u32>,
+    ) -> Vec<SearchItem> {
+        let count = searched.results.len();
+        let mut items: Vec<SearchItem> = searched
+            .results
+            .into_iter()
+            .enumerate()
+            .map(|(index, hit)| {
+                let url = hit.url.clone();
+                let snippet: String = hit.content.chars().take(200).collect();
+                let subtitle = if snippet.is_empty() {
+                    format!("{} ({})", hit.url, hit.engines.join(", "))
+                } else {
+                    format!("{} ({})", snippet, hit.url)
+                };
+                SearchItem {
+                    id: format!("{}:{}", self.name(), hit.url),
+                    title: Some(hit.title),
+                    subtitle: Some(subtitle),
+                    icon: hit.icon,
+                    image: hit.image,
+                    // Results of SearXNG are ordered by relevance already
+                    score: (count - index) as i64,
+                    source: self.name(),
+                    action: Rc::new(move |os| {
+                        let result = os.open_url(&url);
+                        os.report(result)
+                    }),
+                    layer: ItemLayer::Top,
+                }
+            })
+            .collect();
+        let subtitle = format!("{} (SearXNG)", query);
+        if searched.fetching {
+            items.push(self.build_search_item(query, "Searching SearXNG", subtitle, false));
+            return items;
+        }
+        if let Some(error) = searched.error {
+            items.push(SearchItem::error(format!(
+                "Failed to search SearXNG: {}",
+                error
+            )));
+        }
+        if searched.next_page.is_some() {
+            items.push(self.build_search_item(query, "More results", subtitle, true));
+        } else if count == 0 {
+            items.push(self.build_search_item(query, "Search SearXNG", subtitle, false));
+        }
+        items
+    }
+}
+
+impl Source for SearxngSource {
+    fn name(&self) -> &'static str {
+        "searxng"
+    }
+
+    fn init(&mut self, config: &toml::Table, _helpers: Arc<Helpers>) {
+        let config: SearxngConfig = config.clone().try_into().unwrap();
+        self.config = Some(config);
+    }
+
+    fn loading(&self) -> bool {
+        self.searches.loading()
+    }
+
+    fn deinit(&mut self) {}
+
+    fn search(
+        &self,
+        query: &str,
+        _matcher: &Box<dyn fuzzy_matcher::FuzzyMatcher + Send + Sync>,
+    ) -> Vec<SearchItem> {
+        let query = query.trim();
+        if let Some(searched) = self.searches.get(query) {
+            return self.build_result_items(query, searched);
+        }
+        let parsed = SearxngQuery::parse(query);
+        if parsed.text.is_empty() {
+            return vec![];
+        }
+        let selectors = parsed
+            .categories
+            .iter()
+            .chain(&parsed.engines)
+            .map(|name| format!("!{}", name))
+            .collect::<Vec<String>>();
+        let subtitle = if selectors.is_empty() {
+            parsed.text.clone()
+        } else {
+            format!("{} ({})", parsed.text, selectors.join(", "))
+        };
+        vec![self.build_search_item(query, "Search SearXNG", subtitle, false)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::TestServer;
+    use fuzzy_matcher::skim::SkimMatcherV2;
+    use fuzzy_matcher::FuzzyMatcher;
+    use std::time::{Duration, Instant};
+
+    const RESULTS: &str = r#"{"results": [
+        {"url": "https://www.rust-lang.org/", "title": "Rust", "content": "A language",
+         "engines": ["duckduckgo", "brave"]},
+        {"url": "https://doc.rust-lang.org/book/", "title": "The Book", "content": "",
+         "engines": ["brave"], "thumbnail": null}
+    ]}"#;
+
+    fn source(server: &TestServer) -> SearxngSource {
+        let config: toml::Table = toml::from_str(&format!(
+            r#"
+            url = "{}/"
+            categories = ["general"]
+            icons = false
+            "#,
+            server.url
+        ))
+        .unwrap();
+        let mut source = SearxngSource::new();
+        source.init(&config, Arc::new(Helpers::default()));
+        source
+    }
+
+    fn search(source: &SearxngSource, query: &str) -> Vec<(String, String)> {
+        let matcher: Box<dyn FuzzyMatcher + Send + Sync> = Box::new(SkimMatcherV2::default());
+        let mut items = source.search(query, &matcher);
+        items.sort_by_key(|item| std::cmp::Reverse(item.score));
+        items
+            .into_iter()
+            .map(|item| {
+                (
+                    item.title.unwrap_or_default(),
+                    item.subtitle.unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    fn fetch(source: &SearxngSource, query: &str, more: bool) {
+        SearxngSource::fetch(
+            &source.searches,
+            source.config.as_ref().unwrap(),
+            query,
+            more,
+        );
+        let started = Instant::now();
+        while source.loading() {
+            assert!(started.elapsed() < Duration::from_secs(5), "still loading");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn parses_selectors() {
+        let query = SearxngQuery::parse("!images ferris !ddg crab !");
+        assert_eq!(query.text, "ferris crab !");
+        assert_eq!(query.categories, vec!["images"]);
+        assert_eq!(query.engines, vec!["ddg"]);
+    }
+
+    #[test]
+    fn results_are_fetched_page_by_page() {
+        let server = TestServer::start(vec![("/search", "application/json", RESULTS.to_string())]);
+        let source = source(&server);
+        assert_eq!(
+            search(&source, "rust"),
+            vec![("Search SearXNG".to_string(), "rust".to_string())]
+        );
+
+        fetch(&source, "rust", false);
+        let titles: Vec<String> = search(&source, "rust")
+            .into_iter()
+            .map(|item| item.0)
+            .collect();
+        assert_eq!(titles, vec!["Rust", "The Book", "More results"]);
+        assert_eq!(
+            search(&source, "rust")[0].1,
+            "A language (https://www.rust-lang.org/)"
+        );
+        assert_eq!(
+            search(&source, "rust")[1].1,
+            "https://doc.rust-lang.org/book/ (brave)"
+        );
+
+        fetch(&source, "rust", true);
+        assert_eq!(search(&source, "rust").len(), 5);
+        assert_eq!(
+            server.requests(),
+            vec![
+                "GET /search?q=rust&format=json&pageno=1&categories=general",
+                "GET /search?q=rust&format=json&pageno=2&categories=general",
+            ]
+        );
+    }
+
+    #[test]
+    fn selectors_replace_the_configured_categories() {
+        let server = TestServer::start(vec![("/search", "application/json", RESULTS.to_string())]);
+        let source = source(&server);
+        fetch(&source, "!images !bing ferris", false);
+        assert_eq!(
+            server.requests(),
+            vec!["GET /search?q=ferris&format=json&pageno=1&categories=images&engines=bing"]
+        );
+    }
+
+    #[test]
+    fn an_empty_page_is_the_last() {
+        let server = TestServer::start(vec![(
+            "/search",
+            "application/json",
+            r#"{"results": []}"#.to_string(),
+        )]);
+        let source = source(&server);
+        fetch(&source, "nothing", false);
+        let titles: Vec<String> = search(&source, "nothing")
+            .into_iter()
+            .map(|item| item.0)
+            .collect();
+        assert_eq!(titles, vec!["Search SearXNG"]);
+    }
+}
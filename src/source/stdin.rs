@@ -1,18 +1,315 @@
 use crate::helpers::Helpers;
-use crate::model::SearchItem;
+use crate::model::{OSImage, SearchItem};
+use crate::opts::Config;
 use crate::source::Source;
 use atty;
+use freedesktop_icon_lookup::Cache;
 use fuzzy_matcher::FuzzyMatcher;
-use std::io::{stdin, Read};
-use std::sync::Arc;
+use log::warn;
+use serde::Deserialize;
+use std::io::{stdin, BufRead, BufReader};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InputFormat {
+    Plain,
+    /// `text\0key\x1fvalue\x1f...` rows, using the rofi keys `display`, `icon`, `meta` and `info`
+    /// as well as `subtitle` and `image`
+    Rofi,
+    /// One JSON object per line
+    Json,
+}
+
+impl InputFormat {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "plain" => Self::Plain,
+            "rofi" => Self::Rofi,
+            "json" => Self::Json,
+            _ => {
+                warn!("No input format '{name}', falling back to 'plain'");
+                Self::Plain
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonItem {
+    title: String,
+    subtitle: Option<String>,
+    /// Path or icon name
+    icon: Option<String>,
+    image: Option<String>,
+    value: Option<String>,
+}
+
+#[derive(Clone)]
+struct StdinItem {
+    /// Printed when selected
+    value: String,
+    title: String,
+    subtitle: Option<String>,
+    icon: Option<OSImage>,
+    image: Option<OSImage>,
+    /// Matched in addition to the title
+    keywords: Option<String>,
+}
+
+impl StdinItem {
+    fn plain(text: &str) -> Self {
+        Self {
+            value: text.to_string(),
+            title: text.to_string(),
+            subtitle: None,
+            icon: None,
+            image: None,
+            keywords: None,
+        }
+    }
+}
+
+/// Turns lines read from stdin into items
+struct StdinParser {
+    input_format: InputFormat,
+    delimiter: Option<String>,
+    /// 1-based columns forming the title
+    with_nth: Vec<usize>,
+    /// 1-based column used as the subtitle
+    display_subtitle: Option<usize>,
+    icon_cache: Option<Cache>,
+}
+
+impl StdinParser {
+    fn new(config: &Config) -> Self {
+        let delimiter = Some(config.delimiter.replace("\\t", "\t")).filter(|d| !d.is_empty());
+        let with_nth = config
+            .with_nth
+            .split(',')
+            .filter(|column| !column.is_empty())
+            .filter_map(|column| match column.trim().parse::<usize>() {
+                Ok(column) if column > 0 => Some(column),
+                _ => {
+                    warn!("Invalid column '{column}' in --with-nth");
+                    None
+                }
+            })
+            .collect();
+        Self {
+            input_format: InputFormat::from_name(&config.input_format),
+            delimiter,
+            with_nth,
+            display_subtitle: Some(config.display_subtitle).filter(|column| *column > 0),
+            icon_cache: None,
+        }
+    }
+
+    fn parse(&mut self, line: &str) -> Option<StdinItem> {
+        match self.input_format {
+            InputFormat::Plain => match &self.delimiter {
+                Some(delimiter) => Some(self.parse_columns(line, delimiter)),
+                None => Some(StdinItem::plain(line)),
+            },
+            InputFormat::Rofi => Some(self.parse_rofi(line)),
+            InputFormat::Json => self.parse_json(line),
+        }
+    }
+
+    fn parse_columns(&self, line: &str, delimiter: &str) -> StdinItem {
+        let columns: Vec<&str> = line.split(delimiter).collect();
+        let column = |index: usize| columns.get(index - 1).map(|column| column.to_string());
+        let mut item = StdinItem::plain(line);
+        if !self.with_nth.is_empty() {
+            item.title = self
+                .with_nth
+                .iter()
+                .filter_map(|index| column(*index))
+                .collect::<Vec<String>>()
+                .join(" ");
+        }
+        item.subtitle = self.display_subtitle.and_then(column);
+        item
+    }
+
+    fn parse_rofi(&mut self, line: &str) -> StdinItem {
+        let (text, options) = line.split_once('\0').unwrap_or((line, ""));
+        let mut item = StdinItem::plain(text);
+        let options: Vec<&str> = options.split('\x1f').collect();
+        for option in options.chunks(2) {
+            let [key, value] = option else {
+                continue;
+            };
+            match *key {
+                "display" => item.title = value.to_string(),
+                "subtitle" => item.subtitle = Some(value.to_string()),
+                "icon" => item.icon = self.load_icon(value),
+                "image" => item.image = Helpers::load_image(Path::new(value)),
+                "meta" => item.keywords = Some(value.to_string()),
+                "info" => item.value = value.to_string(),
+                _ => {}
+            }
+        }
+        item
+    }
+
+    fn parse_json(&mut self, line: &str) -> Option<StdinItem> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        let json_item: JsonItem = match serde_json::from_str(line) {
+            Ok(json_item) => json_item,
+            Err(err) => {
+                warn!("Skipping invalid JSON line: {}", err);
+                return None;
+            }
+        };
+        Some(StdinItem {
+            value: json_item.value.unwrap_or_else(|| json_item.title.clone()),
+            title: json_item.title,
+            subtitle: json_item.subtitle,
+            icon: json_item.icon.and_then(|icon| self.load_icon(&icon)),
+            image: json_item
+                .image
+                .and_then(|image| Helpers::load_image(Path::new(&image))),
+            keywords: None,
+        })
+    }
+
+    /// Loads an icon from a path or looks up an icon name in the icon theme
+    fn load_icon(&mut self, icon: &str) -> Option<OSImage> {
+        let path = Path::new(icon);
+        if path.exists() {
+            return Helpers::load_image(path);
+        }
+        if self.icon_cache.is_none() {
+            let mut cache = Cache::new().ok()?;
+            if cache.load_default().is_err() {
+                warn!("Failed to load default icon cache");
+            }
+            self.icon_cache = Some(cache);
+        }
+        let icon_path = self.icon_cache.as_ref()?.lookup(icon, None)?;
+        Helpers::load_image(&icon_path)
+    }
+}
+
+struct StdinSourceInner {
+    items: Mutex<Vec<StdinItem>>,
+    reading: AtomicBool,
+}
 
 pub struct StdinSource {
-    items: Vec<String>,
+    inner: Arc<StdinSourceInner>,
+    only_match: bool,
+    separator: u8,
+    parser: Option<StdinParser>,
 }
 
 impl StdinSource {
-    pub fn new() -> Self {
-        Self { items: vec![] }
+    pub fn new(config: &Config) -> Self {
+        Self {
+            inner: Arc::new(StdinSourceInner {
+                items: Mutex::new(vec![]),
+                reading: AtomicBool::new(false),
+            }),
+            only_match: config.only_match,
+            separator: if config.read0 { b'\0' } else { b'\n' },
+            parser: Some(StdinParser::new(config)),
+        }
+    }
+
+    /// Parses `separator` terminated lines from `reader` into `items` until the input ends
+    fn read_items(
+        mut reader: impl BufRead,
+        separator: u8,
+        parser: &mut StdinParser,
+        items: &Mutex<Vec<StdinItem>>,
+    ) {
+        let mut buf = vec![];
+        loop {
+            buf.clear();
+            match reader.read_until(separator, &mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("Failed to read from stdin: {}", err);
+                    break;
+                }
+            }
+            if buf.last() == Some(&separator) {
+                buf.pop();
+            }
+            if separator == b'\n' && buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+            let line = String::from_utf8_lossy(&buf);
+            if let Some(item) = parser.parse(&line) {
+                items.lock().unwrap().push(item);
+            }
+        }
+    }
+
+    /// Formats a selection like rofi's `-format`, `index` is `None` for typed text
+    fn format_output(format: &str, index: Option<usize>, text: &str, query: &str) -> String {
+        format
+            .chars()
+            .map(|c| match c {
+                's' => text.to_string(),
+                'i' => index.map_or("-1".to_string(), |index| index.to_string()),
+                'f' => query.to_string(),
+                'q' => shlex::try_quote(text)
+                    .map(|quoted| quoted.to_string())
+                    .unwrap_or_else(|_| text.to_string()),
+                c => c.to_string(),
+            })
+            .collect()
+    }
+
+    fn build_item(
+        &self,
+        index: Option<usize>,
+        item: &StdinItem,
+        query: &str,
+        score: i64,
+    ) -> SearchItem {
+        let value = item.value.clone();
+        let query = query.to_string();
+        SearchItem {
+            id: self.name().to_string() + &item.value,
+            title: Some(item.title.clone()),
+            subtitle: item.subtitle.clone(),
+            icon: item.icon.clone(),
+            image: item.image.clone(),
+            score,
+            source: self.name(),
+            action: Rc::new(move |os| {
+                let output = Self::format_output(&os.config.format, index, &value, &query);
+                os.print(&output);
+                true
+            }),
+            layer: if index.is_some() {
+                crate::model::ItemLayer::Middle
+            } else {
+                crate::model::ItemLayer::Bottom
+            },
+        }
+    }
+
+    fn match_score(
+        item: &StdinItem,
+        query: &str,
+        matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
+    ) -> i64 {
+        let title_score = matcher.fuzzy_match(&item.title, query).unwrap_or(0);
+        let keywords_score = item
+            .keywords
+            .as_ref()
+            .and_then(|keywords| matcher.fuzzy_match(keywords, query))
+            .unwrap_or(0);
+        title_score.max(keywords_score)
     }
 }
 
@@ -25,12 +322,26 @@ impl Source for StdinSource {
         if atty::is(atty::Stream::Stdin) {
             return;
         }
-        let mut buf = String::new();
-        stdin()
-            .lock()
-            .read_to_string(&mut buf)
-            .expect("Failed to read from stdin");
-        self.items = buf.lines().map(|s| s.to_string()).collect();
+        let Some(mut parser) = self.parser.take() else {
+            return;
+        };
+        let inner = self.inner.clone();
+        let separator = self.separator;
+        inner.reading.store(true, Ordering::SeqCst);
+        // read in a separate thread, so items show up while input is still arriving
+        std::thread::spawn(move || {
+            Self::read_items(
+                BufReader::new(stdin()),
+                separator,
+                &mut parser,
+                &inner.items,
+            );
+            inner.reading.store(false, Ordering::SeqCst);
+        });
+    }
+
+    fn loading(&self) -> bool {
+        self.inner.reading.load(Ordering::SeqCst)
     }
 
     fn deinit(&mut self) {}
@@ -40,28 +351,197 @@ impl Source for StdinSource {
         query: &str,
         matcher: &Box<dyn FuzzyMatcher + Send + Sync>,
     ) -> Vec<SearchItem> {
-        self.items
+        let stdin_items = self.inner.items.lock().unwrap();
+        let mut items: Vec<SearchItem> = stdin_items
             .iter()
-            .map(|s| (s, matcher.fuzzy_match(&s, query).unwrap_or(0)))
-            .filter(|(_, score)| *score > 0 || query.is_empty())
-            .map(|(s, score)| {
-                let text = s.clone();
-                SearchItem {
-                    id: self.name().to_string() + &text,
-                    title: Some(s.clone()),
-                    subtitle: None,
-                    icon: None,
-                    image: None,
-                    score,
-                    source: self.name(),
-                    action: Box::new(move |os| {
-                        let text = text.clone();
-                        os.print(&text);
-                        true
-                    }),
-                    layer: crate::model::ItemLayer::Middle,
-                }
-            })
-            .collect()
+            .enumerate()
+            .map(|(index, item)| (index, item, Self::match_score(item, query, matcher)))
+            .filter(|(_, _, score)| *score > 0 || query.is_empty())
+            .map(|(index, item, score)| self.build_item(Some(index), item, query, score))
+            .collect();
+        // Like dmenu, the typed text can be selected when it is not one of the items
+        if !self.only_match && !query.is_empty() && !stdin_items.iter().any(|s| s.value == query) {
+            items.push(self.build_item(None, &StdinItem::plain(query), query, 0));
+        }
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn parser(input_format: &str, delimiter: &str, with_nth: &str, subtitle: usize) -> StdinParser {
+        StdinParser::new(&Config {
+            input_format: input_format.to_string(),
+            delimiter: delimiter.to_string(),
+            with_nth: with_nth.to_string(),
+            display_subtitle: subtitle,
+            ..Config::default()
+        })
+    }
+
+    fn read(input: &[u8], separator: u8, parser: &mut StdinParser) -> Vec<StdinItem> {
+        let items = Mutex::new(vec![]);
+        StdinSource::read_items(Cursor::new(input), separator, parser, &items);
+        items.into_inner().unwrap()
+    }
+
+    #[test]
+    fn parses_lines() {
+        // (input format, delimiter, --with-nth, --display-subtitle, line, value, title, subtitle, keywords)
+        let cases = [
+            ("plain", "", "", 0, "a b c", "a b c", "a b c", None, None),
+            (
+                "plain", ":", "2", 0, "1:two:3", "1:two:3", "two", None, None,
+            ),
+            (
+                "plain",
+                ":",
+                "3,1",
+                2,
+                "1:two:3",
+                "1:two:3",
+                "3 1",
+                Some("two"),
+                None,
+            ),
+            (
+                "plain",
+                "\\t",
+                "2",
+                1,
+                "id\tname",
+                "id\tname",
+                "name",
+                Some("id"),
+                None,
+            ),
+            (
+                "plain", ":", "", 4, "1:two:3", "1:two:3", "1:two:3", None, None,
+            ),
+            ("plain", ":", "5", 0, "1:two", "1:two", "", None, None),
+            ("rofi", "", "", 0, "text", "text", "text", None, None),
+            (
+                "rofi",
+                "",
+                "",
+                0,
+                "text\0display\x1fShown\x1fsubtitle\x1fSub\x1fmeta\x1fkey words\x1finfo\x1fvalue",
+                "value",
+                "Shown",
+                Some("Sub"),
+                Some("key words"),
+            ),
+            (
+                "rofi",
+                "",
+                "",
+                0,
+                "text\0unknown\x1fx\x1fdisplay",
+                "text",
+                "text",
+                None,
+                None,
+            ),
+            (
+                "json",
+                "",
+                "",
+                0,
+                r#"{"title": "Title", "subtitle": "Sub", "value": "returned"}"#,
+                "returned",
+                "Title",
+                Some("Sub"),
+                None,
+            ),
+            (
+                "json",
+                "",
+                "",
+                0,
+                r#"{"title": "Title"}"#,
+                "Title",
+                "Title",
+                None,
+                None,
+            ),
+        ];
+        for (format, delimiter, with_nth, subtitle, line, value, title, sub, keywords) in cases {
+            let item = parser(format, delimiter, with_nth, subtitle)
+                .parse(line)
+                .unwrap();
+            assert_eq!(item.value, value, "{:?}", line);
+            assert_eq!(item.title, title, "{:?}", line);
+            assert_eq!(item.subtitle.as_deref(), sub, "{:?}", line);
+            assert_eq!(item.keywords.as_deref(), keywords, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn skips_blank_and_invalid_json_lines() {
+        let mut parser = parser("json", "", "", 0);
+        for line in ["", "  ", "not json", r#"{"subtitle": "no title"}"#] {
+            assert!(parser.parse(line).is_none(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn loads_rofi_icons_from_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let icon = dir.path().join("icon.png");
+        image::RgbaImage::new(2, 1).save(&icon).unwrap();
+        let line = format!("text\0icon\x1f{}", icon.display());
+        let item = parser("rofi", "", "", 0).parse(&line).unwrap();
+        assert_eq!(item.icon.map(|icon| icon.dimensions()), Some((2, 1)));
+        assert_eq!(item.title, "text");
+    }
+
+    #[test]
+    fn splits_input_by_separator() {
+        let mut plain = parser("plain", "", "", 0);
+        let titles = |items: Vec<StdinItem>| {
+            items
+                .into_iter()
+                .map(|item| item.title)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            titles(read(b"one\r\ntwo\nthree", b'\n', &mut plain)),
+            ["one", "two", "three"]
+        );
+        assert_eq!(
+            titles(read(b"multi\nline\0two\0", b'\0', &mut plain)),
+            ["multi\nline", "two"]
+        );
+        assert_eq!(titles(read(b"keeps\r\0", b'\0', &mut plain)), ["keeps\r"]);
+        let mut rofi = parser("rofi", "", "", 0);
+        let items = read(b"a\0display\x1fA\nb\n", b'\n', &mut rofi);
+        assert_eq!(titles(items), ["A", "b"]);
+    }
+
+    #[test]
+    fn formats_output() {
+        // (format, index, text, query, output)
+        let cases = [
+            ("s", Some(2), "item", "it", "item"),
+            ("i", Some(2), "item", "it", "2"),
+            ("i", None, "typed", "typed", "-1"),
+            ("f", Some(2), "item", "it", "it"),
+            ("q", Some(0), "two words", "", "'two words'"),
+            ("q", Some(0), "it's", "", "\"it's\""),
+            ("q", Some(0), "plain", "", "plain"),
+            ("i:s", Some(1), "item", "", "1:item"),
+            ("s (f)", Some(1), "item", "it", "item (it)"),
+        ];
+        for (format, index, text, query, output) in cases {
+            assert_eq!(
+                StdinSource::format_output(format, index, text, query),
+                output,
+                "{}",
+                format
+            );
+        }
     }
 }
@@ -1,3 +1,4 @@
+use std::rc::Rc;
 use std::sync::Arc;
 
 use crate::helpers::Helpers;
@@ -61,10 +62,10 @@ impl Source for SystemctlSource {
                     image: None,
                     score,
                     source: self.name(),
-                    action: Box::new(move |os| {
+                    action: Rc::new(move |os| {
                         let command = command.clone();
-                        os.run(&format!("systemctl {}", command));
-                        true
+                        let result = os.run(&format!("systemctl {}", command));
+                        os.report(result)
                     }),
                     layer: crate::model::ItemLayer::Middle,
                 }
@@ -3,6 +3,7 @@ use crate::model::SearchItem;
 use crate::source::Source;
 use fuzzy_matcher::FuzzyMatcher;
 use std::process::Command;
+use std::rc::Rc;
 use std::sync::Arc;
 
 pub struct ZoxideSource {}
@@ -54,12 +55,11 @@ impl Source for ZoxideSource {
                     image: None,
                     score,
                     source: self.name(),
-                    action: Box::new(move |os| {
+                    action: Rc::new(move |os| {
                         let shell = std::env::var("SHELL").unwrap_or("bash".to_string());
-                        let command =
-                            format!("{} -c 'cd \"{}\";exec $SHELL;'", shell, action_directory);
-                        os.run_in_terminal(&command);
-                        true
+                        let result =
+                            os.run_app_in_terminal(&[shell], None, Some(&action_directory));
+                        os.report(result)
                     }),
                     layer: crate::model::ItemLayer::Middle,
                 }
@@ -0,0 +1,88 @@
+//! Minimal HTTP server answering with fixed bodies, standing in for the APIs of network sources
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct TestServer {
+    /// Base URL without a trailing slash, e.g. `http://127.0.0.1:41234`
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    /// Answers each request with the body of the first route whose path prefixes the request
+    /// path, or 404. Routes are `(path, content type, body)`, `{url}` in bodies becomes `url`
+    pub fn start(routes: Vec<(&'static str, &'static str, String)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes: Vec<_> = routes
+            .into_iter()
+            .map(|(path, content_type, body)| (path, content_type, body.replace("{url}", &url)))
+            .collect();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let thread_requests = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                Self::answer(stream, &routes, &thread_requests);
+            }
+        });
+        Self { url, requests }
+    }
+
+    /// Request lines received so far with the body appended, e.g. `POST /html/ q=rust&s=30`
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn answer(
+        mut stream: TcpStream,
+        routes: &[(&'static str, &'static str, String)],
+        requests: &Mutex<Vec<String>>,
+    ) -> Option<()> {
+        let mut reader = BufReader::new(stream.try_clone().ok()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).ok()?;
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).ok()?;
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).ok()?;
+
+        // Recorded before answering, so it is visible once the client got the response
+        let request = format!(
+            "{} {}",
+            request_line.trim_end().trim_end_matches(" HTTP/1.1"),
+            String::from_utf8_lossy(&body)
+        );
+        requests
+            .lock()
+            .unwrap()
+            .push(request.trim_end().to_string());
+
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let response = match routes.iter().find(|(route, _, _)| path.starts_with(route)) {
+            Some((_, content_type, body)) => format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            ),
+            None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        };
+        stream.write_all(response.as_bytes()).ok()
+    }
+}
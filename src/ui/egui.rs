@@ -1,8 +1,12 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use crate::ui::UI;
-use crate::{model::SearchItem, os::Os};
+use crate::{
+    model::{OSImage, SearchItem},
+    os::Os,
+};
 
 use eframe::egui;
 use egui::*;
@@ -41,23 +45,42 @@ impl UI for EguiUI {
     }
 }
 
+/// Uploads an item image so it can be drawn
+fn texture(ctx: &egui::Context, image: &OSImage) -> egui::load::SizedTexture {
+    let color_image = ColorImage::from_rgba_unmultiplied(
+        [image.width() as usize, image.height() as usize],
+        image.as_bytes(),
+    );
+    let size = egui::vec2(color_image.size[0] as f32, color_image.size[1] as f32);
+    let handle = ctx.load_texture("bytes://", color_image, TextureOptions::LINEAR);
+    egui::load::SizedTexture::new(handle.id(), size)
+}
+
 struct App {
     prompt: String,
     os: Rc<RefCell<Os>>,
     items: Vec<SearchItem>,
     text: String,
     selected_index: usize,
+    loading: bool,
+    last_refresh: Instant,
+    /// Items marked with Tab when multi-select is enabled
+    marked: Vec<SearchItem>,
 }
 
 impl App {
     pub fn new(os: Rc<RefCell<Os>>) -> Self {
         let prompt = os.borrow().config.prompt.clone();
+        let loading = os.borrow().loading();
         Self {
             prompt,
             os,
             items: vec![],
             text: String::new(),
             selected_index: 0,
+            loading,
+            last_refresh: Instant::now(),
+            marked: vec![],
         }
     }
     pub fn search(&mut self) {
@@ -68,6 +91,36 @@ impl App {
             .take(os.config.maximum_list_item_count)
             .collect();
         self.selected_index = 0;
+        // Sources may fetch in the background for the new query
+        self.loading = os.loading();
+    }
+
+    /// Marks or unmarks the selected item and moves to the next one
+    pub fn toggle_mark(&mut self) {
+        let Some(item) = self.items.get(self.selected_index) else {
+            return;
+        };
+        match self.marked.iter().position(|marked| marked.id == item.id) {
+            Some(position) => {
+                self.marked.remove(position);
+            }
+            None => self.marked.push(item.clone()),
+        }
+        if self.selected_index + 1 < self.items.len() {
+            self.selected_index += 1;
+        }
+    }
+
+    /// Searches again while sources are loading, keeping the selection
+    pub fn refresh(&mut self) {
+        if self.text.is_empty() && !self.os.borrow().config.initial_search {
+            return;
+        }
+        let selected_index = self.selected_index;
+        self.search();
+        if selected_index < self.items.len() {
+            self.selected_index = selected_index;
+        }
     }
 }
 
@@ -75,12 +128,18 @@ impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.with_layout(Layout::top_down_justified(Align::Min), |ui| {
+                // Take Tab before the text field uses it to move the focus
+                let toggle_mark = self.os.borrow().config.multi
+                    && ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Tab));
                 let res = TextEdit::singleline(&mut self.text)
                     .hint_text(self.prompt.clone())
                     .font(TextStyle::Heading)
                     .lock_focus(true)
                     .margin(Margin::symmetric(4.0, 4.0))
                     .ui(ui);
+                if self.loading {
+                    ui.spinner();
+                }
                 ui.add_space(6.0);
                 if !res.has_focus() {
                     res.request_focus();
@@ -102,12 +161,31 @@ impl eframe::App for App {
                     self.selected_index = self.selected_index - 1;
                     items_changed = true;
                 }
+                if toggle_mark {
+                    self.toggle_mark();
+                    items_changed = true;
+                }
                 if ui.input(|i| i.key_pressed(Key::Enter)) {
+                    let marked = std::mem::take(&mut self.marked);
                     if let Some(item) = self.items.get(self.selected_index) {
                         let mut os = self.os.borrow_mut();
-                        if os.select(&item) {
+                        let selected = if marked.is_empty() {
+                            os.select(&item)
+                        } else {
+                            os.select_many(&marked)
+                        };
+                        if selected {
                             os.deinit();
                             std::process::exit(0);
+                        } else if let Some(error) = os.take_error() {
+                            self.items.insert(0, error);
+                            self.selected_index = 0;
+                            items_changed = true;
+                        } else if let Some(query) = os.take_query() {
+                            drop(os);
+                            self.text = query;
+                            self.search();
+                            items_changed = true;
                         } else {
                             self.items = vec![];
                             self.text = String::new();
@@ -117,7 +195,7 @@ impl eframe::App for App {
                 }
                 if ui.input(|i| i.key_pressed(Key::Escape)) {
                     self.os.borrow_mut().deinit();
-                    std::process::exit(0);
+                    std::process::exit(1);
                 }
 
                 let _scroll_area = ScrollArea::vertical().show(ui, |ui| {
@@ -130,8 +208,11 @@ impl eframe::App for App {
                         }
                         let response = group.show(ui, |ui| {
                             ui.horizontal(|ui| {
-                                let title_text: RichText =
-                                    item.title.clone().unwrap_or_default().into();
+                                let mut title = item.title.clone().unwrap_or_default();
+                                if self.marked.iter().any(|marked| marked.id == item.id) {
+                                    title = format!("✓ {}", title);
+                                }
+                                let title_text: RichText = title.into();
                                 let title_text_height = ui.fonts(|fonts| {
                                     title_text.font_height(fonts, ui.style().as_ref())
                                 });
@@ -147,22 +228,7 @@ impl eframe::App for App {
                                     + subtitle_text_height
                                     + ui.spacing().item_spacing.x;
                                 if let Some(icon) = &item.icon {
-                                    let color_image = ColorImage::from_rgba_unmultiplied(
-                                        [icon.width() as usize, icon.height() as usize],
-                                        icon.as_bytes(),
-                                    );
-                                    let size = egui::vec2(
-                                        color_image.size[0] as f32,
-                                        color_image.size[1] as f32,
-                                    );
-                                    let handle = ctx.load_texture(
-                                        "bytes://",
-                                        color_image,
-                                        TextureOptions::LINEAR,
-                                    );
-                                    let sized_image =
-                                        egui::load::SizedTexture::new(handle.id(), size);
-                                    let image = egui::Image::from_texture(sized_image)
+                                    let image = egui::Image::from_texture(texture(ctx, icon))
                                         .max_width(max_icon_size)
                                         .max_height(max_icon_size);
                                     ui.add(image);
@@ -172,6 +238,13 @@ impl eframe::App for App {
                                 let _height =
                                     ui.with_layout(Layout::top_down_justified(Align::Min), |ui| {
                                         ui.add(title);
+                                        if let Some(image) = &item.image {
+                                            let image =
+                                                egui::Image::from_texture(texture(ctx, image))
+                                                    .max_width(250.0)
+                                                    .max_height(150.0);
+                                            ui.add(image);
+                                        }
                                         ui.add(subtitle);
                                     });
                             });
@@ -183,5 +256,15 @@ impl eframe::App for App {
                 });
             });
         });
+        // Checked after the input, which may have started loading
+        if self.loading {
+            let refresh_interval = Duration::from_millis(100);
+            if self.last_refresh.elapsed() >= refresh_interval {
+                self.loading = self.os.borrow().loading();
+                self.refresh();
+                self.last_refresh = Instant::now();
+            }
+            ctx.request_repaint_after(refresh_interval);
+        }
     }
 }
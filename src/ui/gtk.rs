@@ -11,14 +11,15 @@ use gtk4_layer_shell::{Edge, Layer, LayerShell};
 
 use relm4::gtk::gdk::Key;
 
-use relm4::gtk::glib::Propagation;
+use relm4::gtk::glib::{self, ControlFlow, Propagation};
 
 use relm4::gtk::{Align, EventControllerKey, Justification, PolicyType};
 use relm4::{
     prelude::*,
     typed_view::list::{RelmListItem, TypedListView},
 };
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::time::Duration;
 pub struct GtkUI {
     os: Rc<RefCell<Os>>,
 }
@@ -38,6 +39,13 @@ impl GtkUI {
     }
 }
 
+/// A list row, marked items are selected together when multi-select is enabled
+#[derive(Clone)]
+pub struct GtkSearchItem {
+    item: SearchItem,
+    marked: bool,
+}
+
 pub struct SearchItemWidgets {
     icon: gtk::Image,
     title: gtk::Label,
@@ -45,27 +53,30 @@ pub struct SearchItemWidgets {
     subtitle: gtk::Label,
 }
 
-impl RelmListItem for SearchItem {
+impl RelmListItem for GtkSearchItem {
     type Root = gtk::Box;
     type Widgets = SearchItemWidgets;
 
     fn bind(&mut self, widgets: &mut Self::Widgets, _root: &mut Self::Root) {
-        widgets.title.set_visible(self.title.is_some());
-        widgets.image.set_visible(self.image.is_some());
-        widgets.subtitle.set_visible(self.subtitle.is_some());
+        widgets.title.set_visible(self.item.title.is_some());
+        widgets.image.set_visible(self.item.image.is_some());
+        widgets.subtitle.set_visible(self.item.subtitle.is_some());
 
-        if let Some(title) = &self.title {
+        if let Some(title) = &self.item.title {
             // TODO: use variable from ui.gtk.char width
-            let title: String = title.chars().take(50).collect();
+            let mut title: String = title.chars().take(50).collect();
+            if self.marked {
+                title = format!("✓ {}", title);
+            }
             widgets.title.set_label(&title);
         }
-        if let Some(subtitle) = &self.subtitle {
+        if let Some(subtitle) = &self.item.subtitle {
             let subtitle: String = subtitle.chars().take(50).collect();
             widgets.subtitle.set_label(&subtitle);
         }
 
-        if self.icon.is_some() {
-            let image = self.icon.as_ref().unwrap();
+        if self.item.icon.is_some() {
+            let image = self.item.icon.as_ref().unwrap();
             let raw_bytes = image.as_raw().as_ref();
             let image_bytes =
                 relm4::gtk::glib::Bytes::from_static(unsafe { std::mem::transmute(raw_bytes) });
@@ -84,8 +95,8 @@ impl RelmListItem for SearchItem {
             );
             widgets.icon.set_from_pixbuf(Some(&pixbuf));
         }
-        if self.image.is_some() {
-            let image = self.image.as_ref().unwrap();
+        if self.item.image.is_some() {
+            let image = self.item.image.as_ref().unwrap();
             let raw_bytes = image.as_raw().as_ref();
             let image_bytes =
                 relm4::gtk::glib::Bytes::from_static(unsafe { std::mem::transmute(raw_bytes) });
@@ -166,8 +177,12 @@ impl RelmListItem for SearchItem {
 
 struct GtkApp {
     os: Rc<RefCell<Os>>,
-    search_items: TypedListView<SearchItem, gtk::SingleSelection>,
+    search_items: TypedListView<GtkSearchItem, gtk::SingleSelection>,
     search_entry: SearchEntry,
+    spinner: gtk::Spinner,
+    marked: Vec<SearchItem>,
+    /// Whether results are refreshed periodically until the sources finished loading
+    refreshing: Rc<Cell<bool>>,
 }
 
 impl GtkApp {
@@ -178,10 +193,78 @@ impl GtkApp {
             .search(query)
             .into_iter()
             .take(self.os.borrow().config.maximum_list_item_count)
+            .map(|item| GtkSearchItem {
+                marked: self.is_marked(&item),
+                item,
+            })
             .collect();
         self.search_items.clear();
         self.search_items.extend_from_iter(search_items);
     }
+
+    fn is_marked(&self, item: &SearchItem) -> bool {
+        self.marked.iter().any(|marked| marked.id == item.id)
+    }
+
+    /// Marks or unmarks the selected item and moves to the next one
+    pub fn toggle_mark(&mut self) {
+        let selected = self.search_items.selection_model.selected();
+        let Some(row) = self.search_items.get(selected) else {
+            return;
+        };
+        let mut row = row.borrow().clone();
+        match self
+            .marked
+            .iter()
+            .position(|marked| marked.id == row.item.id)
+        {
+            Some(position) => {
+                self.marked.remove(position);
+            }
+            None => self.marked.push(row.item.clone()),
+        }
+        row.marked = !row.marked;
+        // Replace the row so it is bound again with the new mark
+        self.search_items.remove(selected);
+        self.search_items.insert(selected, row);
+        let next = (selected + 1).min(self.search_items.selection_model.n_items() - 1);
+        self.search_items.selection_model.set_selected(next);
+    }
+
+    /// Refreshes the results while sources are loading, e.g. after a search started a fetch
+    fn watch_loading(&self, sender: &ComponentSender<Self>) {
+        if self.refreshing.get() || !self.os.borrow().loading() {
+            return;
+        }
+        self.refreshing.set(true);
+        self.spinner.set_visible(true);
+        let os = self.os.clone();
+        let refreshing = self.refreshing.clone();
+        let sender = sender.clone();
+        glib::timeout_add_local(Duration::from_millis(100), move || {
+            let loading = os.borrow().loading();
+            sender.input(Msg::Refresh);
+            if loading {
+                ControlFlow::Continue
+            } else {
+                refreshing.set(false);
+                ControlFlow::Break
+            }
+        });
+    }
+
+    /// Searches again while sources are loading, keeping the selection
+    pub fn refresh(&mut self) {
+        let query = self.search_entry.text().to_string();
+        if query.is_empty() && !self.os.borrow().config.initial_search {
+            return;
+        }
+        let selected = self.search_items.selection_model.selected();
+        self.search(&query);
+        if selected < self.search_items.selection_model.n_items() {
+            self.search_items.selection_model.set_selected(selected);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -190,6 +273,8 @@ pub enum Msg {
     Up,
     Down,
     Select,
+    Refresh,
+    ToggleMark,
 }
 
 #[relm4::component]
@@ -224,7 +309,7 @@ impl SimpleComponent for GtkApp {
                             match keyval {
                                 Key::Escape => {
                                     os_for_key_pressed.borrow_mut().deinit();
-                                    std::process::exit(0);
+                                    std::process::exit(1);
                                 },
                                 Key::Up => {
                                     sender2.input(Msg::Up);
@@ -234,6 +319,10 @@ impl SimpleComponent for GtkApp {
                                     sender2.input(Msg::Down);
                                     Propagation::Stop
                                 },
+                                Key::Tab if multi => {
+                                    sender2.input(Msg::ToggleMark);
+                                    Propagation::Stop
+                                },
                                 _ => Propagation::Proceed,
                             }
                         });
@@ -241,6 +330,12 @@ impl SimpleComponent for GtkApp {
                     }
                 },
 
+                #[name="spinner"]
+                gtk::Spinner {
+                    set_spinning: true,
+                    set_visible: false,
+                },
+
                 #[name="scroll_items"]
                 gtk::ScrolledWindow {
                     set_policy: (PolicyType::Automatic, PolicyType::Automatic),
@@ -266,7 +361,8 @@ impl SimpleComponent for GtkApp {
         let prompt = os.borrow().config.prompt.clone();
         let initial_search: bool = os.borrow().config.initial_search;
         let os_for_key_pressed = os.clone();
-        let search_items: TypedListView<SearchItem, gtk::SingleSelection> = TypedListView::new();
+        let multi = os.borrow().config.multi;
+        let search_items: TypedListView<GtkSearchItem, gtk::SingleSelection> = TypedListView::new();
         let search_items_box = &search_items.view;
         let widgets = view_output!();
         #[cfg(feature = "wayland")]
@@ -282,21 +378,36 @@ impl SimpleComponent for GtkApp {
         };
         let search_entry = widgets.search_entry.clone();
         search_entry.set_placeholder_text(Some(&prompt));
+        let spinner = widgets.spinner.clone();
         let mut model = GtkApp {
             os,
             search_items,
             search_entry,
+            spinner,
+            marked: vec![],
+            refreshing: Rc::new(Cell::new(false)),
         };
         if initial_search {
             model.search("");
         }
+        // Refresh the results while items are still arriving
+        model.watch_loading(&sender);
         ComponentParts { model, widgets }
     }
 
-    fn update(&mut self, message: Self::Input, _sender: ComponentSender<Self>) {
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>) {
         match message {
             Msg::Search(query) => {
                 self.search(&query);
+                // Sources may fetch in the background for the new query
+                self.watch_loading(&sender);
+            }
+            Msg::ToggleMark => {
+                self.toggle_mark();
+            }
+            Msg::Refresh => {
+                self.spinner.set_visible(self.os.borrow().loading());
+                self.refresh();
             }
             Msg::Up => {
                 self.search_items.view.grab_focus();
@@ -323,18 +434,44 @@ impl SimpleComponent for GtkApp {
                 self.search_entry.grab_focus();
             }
             Msg::Select => {
-                let item = {
-                    let selection = &self.search_items.selection_model;
-                    let selected = selection.selected();
-                    if selected >= selection.n_items() {
-                        return
-                    }
-                    self.search_items.get(selected).unwrap()
+                let marked = std::mem::take(&mut self.marked);
+                let selected = if marked.is_empty() {
+                    let item = {
+                        let selection = &self.search_items.selection_model;
+                        let selected = selection.selected();
+                        if selected >= selection.n_items() {
+                            return;
+                        }
+                        self.search_items.get(selected).unwrap()
+                    };
+                    let item = item.borrow().item.clone();
+                    self.os.borrow_mut().select(&item)
+                } else {
+                    self.os.borrow_mut().select_many(&marked)
                 };
                 let mut os_borrow = self.os.borrow_mut();
-                if os_borrow.select(&item.borrow()) {
+                if selected {
                     os_borrow.deinit();
                     std::process::exit(0);
+                } else if let Some(error) = os_borrow.take_error() {
+                    let error = GtkSearchItem {
+                        item: error,
+                        marked: false,
+                    };
+                    self.search_items.insert(0, error);
+                    self.search_items.selection_model.set_selected(0);
+                } else if let Some(query) = os_borrow.take_query() {
+                    drop(os_borrow);
+                    if self.search_entry.text() == query {
+                        // Setting the same text does not emit the changed signal
+                        self.search(&query);
+                    } else {
+                        // Searches through the changed signal of the entry
+                        self.search_entry.set_text(&query);
+                        self.search_entry.set_position(-1);
+                    }
+                    // The action may have started a fetch, e.g. of more results
+                    self.watch_loading(&sender);
                 } else {
                     self.search_entry.set_text("");
                     self.search_items.clear();
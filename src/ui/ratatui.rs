@@ -6,10 +6,13 @@ use ratatui::prelude::*;
 use ratatui::widgets::{Block, Borders, Paragraph, Scrollbar, ScrollbarState};
 use std::io;
 use std::io::{stdout, Stdout};
+use std::time::Duration;
 use tui_input::backend::crossterm::EventHandler;
 use tui_input::Input;
 use tui_widget_list::{List, ListState, ListableWidget};
 
+const SPINNER_FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
 /// A type alias for the terminal type used in this application
 pub type Tui = Terminal<CrosstermBackend<Stdout>>;
 
@@ -34,6 +37,11 @@ struct App {
     pub os: Os,
     pub list: ListState,
     pub scroll_state: ScrollbarState,
+    pub needs_clear: bool,
+    pub loading: bool,
+    pub spinner_frame: usize,
+    /// Items marked with Tab when multi-select is enabled
+    pub marked: Vec<SearchItem>,
 }
 
 impl App {
@@ -50,11 +58,47 @@ impl App {
             .scroll_state
             .position(0)
             .content_length(self.items.len());
+        // Sources may fetch in the background for the new query
+        self.loading = self.os.loading();
+    }
+
+    /// Searches again while sources are loading, keeping the selection
+    pub fn refresh(&mut self) {
+        if self.input.value().is_empty() && !self.os.config.initial_search {
+            return;
+        }
+        let selected = self.list.selected().unwrap_or(0);
+        self.search();
+        if selected < self.items.len() {
+            self.list.select(Some(selected));
+            self.scroll_state = self.scroll_state.position(selected);
+        }
+    }
+
+    /// Marks or unmarks the selected item and moves to the next one
+    pub fn toggle_mark(&mut self) {
+        let Some(item) = self.list.selected().and_then(|index| self.items.get(index)) else {
+            return;
+        };
+        match self.marked.iter().position(|marked| marked.id == item.id) {
+            Some(position) => {
+                self.marked.remove(position);
+            }
+            None => self.marked.push(item.clone()),
+        }
+        self.list.next();
+        self.scroll_state.next();
     }
 
     pub fn exit(&self) {
         restore().unwrap();
     }
+
+    /// Re-enters the terminal after `exit` if the app keeps running
+    pub fn resume(&mut self) {
+        init().unwrap();
+        self.needs_clear = true;
+    }
 }
 
 pub struct RatatuiUI {
@@ -68,6 +112,7 @@ impl RatatuiUI {
         let mut list = ListState::default().circular(false);
         list.select(Some(0));
         let scroll_state = ScrollbarState::default();
+        let loading = os.loading();
         RatatuiUI {
             app: App {
                 items: vec![],
@@ -76,6 +121,10 @@ impl RatatuiUI {
                 os,
                 list,
                 scroll_state,
+                needs_clear: false,
+                loading,
+                spinner_frame: 0,
+                marked: vec![],
             },
             tui,
         }
@@ -89,13 +138,19 @@ impl RatatuiUI {
             .split(frame.size());
         let width = chunks[0].width.max(3) - 3; // keep 2 for borders and 1 for cursor
         let scroll = app.input.visual_scroll(width as usize);
+        let title = if app.loading {
+            let frame = SPINNER_FRAMES[app.spinner_frame % SPINNER_FRAMES.len()];
+            format!("{} {}", app.prompt, frame)
+        } else {
+            app.prompt.clone()
+        };
         let input = Paragraph::new(app.input.value())
             .scroll((0, scroll as u16))
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_type(ratatui::widgets::BorderType::Rounded)
-                    .title(app.prompt.clone())
+                    .title(title)
                     .style(Style::default().fg(Color::Reset)),
             )
             .fg(Color::Yellow);
@@ -105,7 +160,11 @@ impl RatatuiUI {
             app.items
                 .iter()
                 .map(|item| TuiSearchItem {
-                    title: item.title.clone().unwrap_or("".to_string()),
+                    title: if app.marked.iter().any(|marked| marked.id == item.id) {
+                        format!("✓ {}", item.title.clone().unwrap_or_default())
+                    } else {
+                        item.title.clone().unwrap_or("".to_string())
+                    },
                     subtitle: item.subtitle.clone().unwrap_or("".to_string()),
                     title_style: Style::default().bold(),
                     subtitle_style: Style::default().fg(Color::Gray).italic(),
@@ -133,6 +192,13 @@ impl RatatuiUI {
     }
 
     fn handle_events(app: &mut App) -> io::Result<()> {
+        // Poll instead of blocking, so new items show up while sources are loading
+        if app.loading && !event::poll(Duration::from_millis(100))? {
+            app.loading = app.os.loading();
+            app.spinner_frame += 1;
+            app.refresh();
+            return Ok(());
+        }
         match event::read()? {
             // it's important to check that the event is a key press event as
             // crossterm also emits key release and repeat events on Windows.
@@ -141,19 +207,45 @@ impl RatatuiUI {
                     event::KeyCode::Esc => {
                         app.os.deinit();
                         app.exit();
-                        std::process::exit(0);
+                        std::process::exit(1);
                     }
                     event::KeyCode::Enter => {
+                        let selected_item = app.list.selected().and_then(|i| app.items.get(i));
+                        if app.marked.is_empty() && selected_item.is_none() {
+                            // Nothing matches, exit like Esc does
+                            app.os.deinit();
+                            app.exit();
+                            std::process::exit(1);
+                        }
                         app.exit();
-                        let item = app.items.get(app.list.selected().unwrap()).unwrap();
-                        if app.os.select(item) {
+                        let selected = if app.marked.is_empty() {
+                            let item = app.items.get(app.list.selected().unwrap()).unwrap();
+                            app.os.select(item)
+                        } else {
+                            app.os.select_many(&app.marked)
+                        };
+                        app.marked.clear();
+                        if selected {
                             app.os.deinit();
                             std::process::exit(0);
+                        }
+                        app.resume();
+                        if let Some(error) = app.os.take_error() {
+                            app.items.insert(0, error);
+                            app.list.select(Some(0));
+                            app.scroll_state =
+                                app.scroll_state.position(0).content_length(app.items.len());
+                        } else if let Some(query) = app.os.take_query() {
+                            app.input = Input::new(query);
+                            app.search();
                         } else {
                             app.input.reset();
                             app.items.clear();
                         }
                     }
+                    event::KeyCode::Tab if app.os.config.multi => {
+                        app.toggle_mark();
+                    }
                     event::KeyCode::Down => {
                         app.list.next();
                         app.scroll_state.next();
@@ -215,6 +307,10 @@ impl UI for RatatuiUI {
             self.app.search();
         }
         loop {
+            if self.app.needs_clear {
+                self.tui.clear().unwrap();
+                self.app.needs_clear = false;
+            }
             self.tui
                 .draw(|frame| Self::render_frame(frame, &mut self.app))
                 .unwrap();